@@ -0,0 +1,270 @@
+use std::{
+    any::TypeId,
+    borrow::Cow,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use super::{DefaultProgress, InnerProgress, InnerStep, ProgressEvent, events, pop_steps};
+use crate::{Progress, Step};
+
+/// Used to give a unique id to every branch.
+static NEXT_BRANCH_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct BranchId(u64);
+
+impl BranchId {
+    pub fn new() -> Self {
+        BranchId(NEXT_BRANCH_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+/// A stack of steps running concurrently with the other stacks, see [`DefaultProgress::fork`].
+pub(crate) struct Branch {
+    pub id: BranchId,
+    pub name: Cow<'static, str>,
+    /// The names of the step the branch is attached to and all its parents.
+    /// The steps of the branch are recorded as the children of this step.
+    pub path: Vec<Cow<'static, str>>,
+    pub steps: Vec<InnerStep>,
+    /// A finished branch is kept until the step it's attached to leaves the stack
+    /// so it still counts as done in the percentage of its parent.
+    pub finished: bool,
+}
+
+impl Branch {
+    pub fn new(id: BranchId, name: Cow<'static, str>, path: Vec<Cow<'static, str>>) -> Self {
+        Self {
+            id,
+            name,
+            path,
+            steps: Vec::new(),
+            finished: false,
+        }
+    }
+}
+
+/// A handle on a branch of a [`DefaultProgress`] that owns its own stack of steps.
+///
+/// It's created with [`DefaultProgress::fork`] or [`ProgressBranch::fork`] and works like a [`DefaultProgress`]
+/// except that updating it only replaces the steps of its own stack. The branch is finished when the
+/// handle is dropped, or before that if the step it's attached to leaves the stack, in which case the
+/// following updates are ignored.
+pub struct ProgressBranch {
+    pub(super) progress: DefaultProgress,
+    pub(super) id: BranchId,
+}
+
+impl DefaultProgress {
+    /// Fork a new branch with its own stack of steps, typically to follow a task running on another thread.
+    ///
+    /// The branch is attached to the deepest step currently running: its steps are displayed in the
+    /// view of this step, are recorded as its children in the durations, and the average of all the
+    /// branches of the step, including the finished ones, counts as its current state in the global percentage.
+    /// The time spent in the branches is not subtracted from the self duration of their parent since they run concurrently.
+    ///
+    /// If no step is running, the branch is attached to the progress itself and is displayed in [`super::ProgressView::branches`].
+    /// Once finished, it's forgotten and only counts as done in the global percentage until all the branches attached to
+    /// the progress itself are finished, so a long-lived progress can fork a branch per task.
+    ///
+    /// ```
+    /// # use steppe::{make_enum_progress, Progress};
+    /// # use steppe::default::DefaultProgress;
+    /// make_enum_progress! {
+    ///     pub enum IndexingSteps {
+    ///         IndexingShards,
+    ///         Merging,
+    ///     }
+    /// }
+    /// make_enum_progress! {
+    ///     pub enum ShardSteps {
+    ///         Extracting,
+    ///         Writing,
+    ///     }
+    /// }
+    ///
+    /// let progress = DefaultProgress::default();
+    /// progress.update(IndexingSteps::IndexingShards);
+    /// std::thread::scope(|s| {
+    ///     for shard in 0..4 {
+    ///         let branch = progress.fork(format!("shard {shard}"));
+    ///         s.spawn(move || {
+    ///             branch.update(ShardSteps::Extracting);
+    ///             branch.update(ShardSteps::Writing);
+    ///         });
+    ///     }
+    /// });
+    /// progress.update(IndexingSteps::Merging);
+    /// ```
+    pub fn fork(&self, name: impl Into<Cow<'static, str>>) -> ProgressBranch {
+        self.fork_branch(None, name.into())
+    }
+
+    fn fork_branch(&self, parent: Option<BranchId>, name: Cow<'static, str>) -> ProgressBranch {
+        let id = BranchId::new();
+        let mut inner = self.steps.write().unwrap();
+        let InnerProgress {
+            steps, branches, ..
+        } = &mut *inner;
+
+        match parent {
+            None => {
+                let path: Vec<_> = steps.iter().map(|step| step.step.name()).collect();
+                match steps.last_mut() {
+                    Some(step) => step.branches.push(Branch::new(id, name, path)),
+                    None => branches.push(Branch::new(id, name, path)),
+                }
+            }
+            Some(parent) => match find_branch(branches, steps, parent) {
+                Some((siblings, idx)) if !siblings[idx].finished => {
+                    let parent = &mut siblings[idx];
+                    let path: Vec<_> = parent
+                        .path
+                        .iter()
+                        .cloned()
+                        .chain(parent.steps.iter().map(|step| step.step.name()))
+                        .collect();
+                    match parent.steps.last_mut() {
+                        Some(step) => step.branches.push(Branch::new(id, name, path)),
+                        None => siblings.push(Branch::new(id, name, path)),
+                    }
+                }
+                // The parent branch is finished, the new one is finished as well.
+                _ => (),
+            },
+        }
+
+        drop(inner);
+        self.changes.notify();
+        ProgressBranch {
+            progress: self.clone(),
+            id,
+        }
+    }
+
+    /// Pop all the steps of the branch and mark it as finished.
+    fn finish_branch(&self, id: BranchId) {
+        // Don't panic again if we're unwinding after a panic that poisoned the lock.
+        let Ok(mut inner) = self.steps.write() else {
+            return;
+        };
+        let InnerProgress {
+            steps,
+            branches,
+            finished_branches,
+            durations,
+            timeline,
+            listeners,
+            ..
+        } = &mut *inner;
+
+        let now = jiff::Timestamp::now();
+        let popped = durations.len();
+        // The branches attached to the progress itself are only counted, there is no step to clean them up.
+        if let Some(idx) = branches.iter().position(|branch| branch.id == id) {
+            let Branch { path, steps, .. } = &mut branches.remove(idx);
+            pop_steps(path, steps, durations, timeline, now, 0);
+            *finished_branches = match branches.is_empty() {
+                true => 0,
+                false => *finished_branches + 1,
+            };
+        } else if let Some((siblings, idx)) = find_branch(branches, steps, id) {
+            let Branch {
+                path,
+                steps,
+                finished,
+                ..
+            } = &mut siblings[idx];
+            pop_steps(path, steps, durations, timeline, now, 0);
+            *finished = true;
+        } else {
+            return;
+        }
+
+        let events: Vec<_> = durations[popped..]
+            .iter()
+            .map(ProgressEvent::exited)
+            .collect();
+        let listeners = listeners.to_vec();
+        drop(inner);
+        self.changes.notify();
+        events::notify(&listeners, &events);
+    }
+}
+
+impl ProgressBranch {
+    /// Update the progress of the current step of the branch, see [`DefaultProgress::update`].
+    pub fn update<P: Step>(&self, sub_progress: P) {
+        self.update_boxed(Box::new(sub_progress), TypeId::of::<P>());
+    }
+
+    /// Same as [`ProgressBranch::update`] with a step whose type has been erased,
+    /// `step_type` must be the [`TypeId`] of the original step.
+    pub fn update_boxed(&self, sub_progress: Box<dyn Step>, step_type: TypeId) {
+        self.progress
+            .push_step(Some(self.id), sub_progress, step_type);
+    }
+
+    /// Fork a branch attached to the deepest step of this branch, see [`DefaultProgress::fork`].
+    ///
+    /// If this branch has no step running, the new branch is attached next to it.
+    pub fn fork(&self, name: impl Into<Cow<'static, str>>) -> ProgressBranch {
+        self.progress.fork_branch(Some(self.id), name.into())
+    }
+
+    /// Pop all the steps of the branch. Same as dropping the handle.
+    pub fn finish(self) {}
+
+    /// Returns `true` once the whole progress has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.progress.is_cancelled()
+    }
+}
+
+impl Progress for ProgressBranch {
+    fn update(&self, sub_progress: impl Step) {
+        self.update(sub_progress);
+    }
+
+    fn update_boxed(&self, sub_progress: Box<dyn Step>, type_id: TypeId) {
+        self.update_boxed(sub_progress, type_id);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.is_cancelled()
+    }
+}
+
+impl Drop for ProgressBranch {
+    fn drop(&mut self) {
+        self.progress.finish_branch(self.id);
+    }
+}
+
+/// Search a branch among the branches and recursively in the branches attached to their steps and to the steps.
+///
+/// Returns the branches containing it with its index.
+pub(crate) fn find_branch<'a>(
+    branches: &'a mut Vec<Branch>,
+    steps: &'a mut [InnerStep],
+    id: BranchId,
+) -> Option<(&'a mut Vec<Branch>, usize)> {
+    if let Some(idx) = branches.iter().position(|branch| branch.id == id) {
+        return Some((branches, idx));
+    }
+    for branch in branches.iter_mut() {
+        if let Some(found) = find_branch_in_steps(&mut branch.steps, id) {
+            return Some(found);
+        }
+    }
+    find_branch_in_steps(steps, id)
+}
+
+fn find_branch_in_steps(
+    steps: &mut [InnerStep],
+    id: BranchId,
+) -> Option<(&mut Vec<Branch>, usize)> {
+    steps
+        .iter_mut()
+        .find_map(|step| find_branch(&mut step.branches, &mut [], id))
+}
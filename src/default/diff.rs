@@ -0,0 +1,168 @@
+use std::io;
+
+use indexmap::IndexMap;
+use jiff::{
+    SignedDuration,
+    fmt::friendly::{Designator, Direction, SpanPrinter},
+};
+use serde::{Serialize, Serializer};
+
+use super::{StepDuration, view::get_color_from_percentage};
+
+/// The differences between the durations of two runs, as returned by [`super::DefaultProgress::accumulated_durations`].
+///
+/// It's useful to catch performance regressions between two versions of a program.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProfileDiff {
+    /// The steps of the new run in order, followed by the steps that only existed in the old one.
+    pub steps: IndexMap<String, StepDiff>,
+}
+
+/// How a step changed between the two runs.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum StepDiff {
+    /// The step exists in both runs.
+    #[serde(rename_all = "camelCase")]
+    Changed {
+        total_duration: DurationDelta,
+        self_duration: DurationDelta,
+    },
+    /// The step only exists in the new run.
+    Added { after: StepDuration },
+    /// The step only exists in the old run.
+    Removed { before: StepDuration },
+}
+
+/// The change of a single duration between the two runs.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DurationDelta {
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
+    pub before: SignedDuration,
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
+    pub after: SignedDuration,
+    /// `after - before`, positive when the step got slower.
+    #[serde(serialize_with = "serialize_signed_duration")]
+    pub absolute: SignedDuration,
+    /// The change in percent of the old duration. `None` if the step took no time in the old run.
+    pub relative: Option<f64>,
+}
+
+impl DurationDelta {
+    fn new(before: SignedDuration, after: SignedDuration) -> Self {
+        let relative = (!before.is_zero())
+            .then(|| (after - before).as_secs_f64() / before.as_secs_f64() * 100.0);
+        Self {
+            before,
+            after,
+            absolute: after - before,
+            relative,
+        }
+    }
+}
+
+impl ProfileDiff {
+    /// Compare the durations of an old run with the durations of a new run.
+    pub fn new(
+        before: &IndexMap<String, StepDuration>,
+        after: &IndexMap<String, StepDuration>,
+    ) -> Self {
+        let mut steps = IndexMap::with_capacity(after.len());
+        for (name, new) in after {
+            let diff = match before.get(name) {
+                Some(old) => StepDiff::Changed {
+                    total_duration: DurationDelta::new(old.total_duration, new.total_duration),
+                    self_duration: DurationDelta::new(old.self_duration, new.self_duration),
+                },
+                None => StepDiff::Added { after: new.clone() },
+            };
+            steps.insert(name.clone(), diff);
+        }
+        for (name, old) in before {
+            if !after.contains_key(name) {
+                steps.insert(
+                    name.clone(),
+                    StepDiff::Removed {
+                        before: old.clone(),
+                    },
+                );
+            }
+        }
+        Self { steps }
+    }
+
+    /// Write the differences with a line per step, colored depending on how much slower the step got.
+    pub fn write_colored(&self, mut writer: impl io::Write) -> io::Result<()> {
+        const BLUE: &str = "\x1b[34;1m";
+        const RESET_COLOR: &str = "\x1b[m";
+
+        for (name, diff) in &self.steps {
+            write!(writer, "{BLUE}{name}{RESET_COLOR} => ")?;
+            match diff {
+                StepDiff::Changed {
+                    total_duration,
+                    self_duration,
+                } => {
+                    write_delta(&mut writer, "total", total_duration)?;
+                    write!(writer, " ")?;
+                    write_delta(&mut writer, "self", self_duration)?;
+                    writeln!(writer)?;
+                }
+                StepDiff::Added { after } => {
+                    let total = after.total_duration;
+                    let color = get_color_from_percentage(100.0);
+                    writeln!(writer, "{color}added, total: {total:?}{RESET_COLOR}")?;
+                }
+                StepDiff::Removed { before } => {
+                    let total = before.total_duration;
+                    let color = get_color_from_percentage(0.0);
+                    writeln!(writer, "{color}removed, total: {total:?}{RESET_COLOR}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Print the differences on the stdout, see [`ProfileDiff::write_colored`].
+    pub fn print_on_tty(&self) {
+        self.write_colored(io::stdout().lock()).unwrap();
+    }
+}
+
+fn write_delta(writer: &mut impl io::Write, label: &str, delta: &DurationDelta) -> io::Result<()> {
+    const RESET_COLOR: &str = "\x1b[m";
+
+    let DurationDelta {
+        before,
+        after,
+        absolute,
+        relative,
+    } = delta;
+    // Only the regressions are highlighted, a step that got faster is always grayed out.
+    let color = get_color_from_percentage(relative.unwrap_or(100.0).clamp(0.0, 99.99));
+    let absolute = SIGNED_PRINTER.duration_to_string(absolute);
+    match relative {
+        Some(relative) => write!(
+            writer,
+            "{color}{label}: {before:?} -> {after:?} ({absolute}, {relative:+.2}%){RESET_COLOR}"
+        ),
+        None => write!(
+            writer,
+            "{color}{label}: {before:?} -> {after:?} ({absolute}){RESET_COLOR}"
+        ),
+    }
+}
+
+/// Prints the durations as `+1s` or `-500ms` instead of `500ms ago`.
+const SIGNED_PRINTER: SpanPrinter = SpanPrinter::new()
+    .designator(Designator::Compact)
+    .direction(Direction::ForceSign);
+
+fn serialize_signed_duration<S: Serializer>(
+    duration: &SignedDuration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&SIGNED_PRINTER.duration_to_string(duration))
+}
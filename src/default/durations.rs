@@ -0,0 +1,183 @@
+use std::borrow::Cow;
+
+use indexmap::IndexMap;
+use jiff::SignedDuration;
+use serde::{Deserialize, Serialize};
+
+/// A single passage of a step through the stack.
+/// It's recorded every time a step is popped from the stack.
+pub(crate) struct StepOccurrence {
+    /// The names of the step and all its parents, starting from the root.
+    pub path: Vec<Cow<'static, str>>,
+    pub total_duration: SignedDuration,
+    pub self_duration: SignedDuration,
+    /// Whether the step was running when the progress was cancelled.
+    pub cancelled: bool,
+    /// The state of the step if it didn't move while it was on the stack.
+    pub state: Option<StepState>,
+}
+
+/// The `current` and `total` of a step that didn't move while it was on the stack, e.g. a variant of an enum.
+///
+/// Its name designates one of the states of the step instead of a task moving by itself,
+/// see [`super::DefaultProgress::with_profile`].
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct StepState {
+    pub current: u64,
+    pub total: u64,
+}
+
+impl StepOccurrence {
+    /// The name of the step and all its parents joined with `" > "`.
+    pub fn full_name(&self) -> String {
+        self.path.join(" > ")
+    }
+}
+
+/// Sum all the occurrences of the same path together.
+/// The order of the map is the order in which each path was first seen.
+pub(crate) fn aggregate<'a>(
+    occurrences: impl IntoIterator<Item = &'a StepOccurrence>,
+) -> IndexMap<String, StepDuration> {
+    let mut durations: IndexMap<String, StepDuration> = IndexMap::new();
+    for occurrence in occurrences {
+        durations
+            .entry(occurrence.full_name())
+            .and_modify(|duration| duration.add_occurrence(occurrence))
+            .or_insert_with(|| StepDuration::from_occurrence(occurrence));
+    }
+    durations
+}
+
+/// The durations of a step accumulated over all the times it was entered.
+///
+/// It can be deserialized to seed a new progress with the durations of a previous run, see [`super::DefaultProgress::with_profile`].
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StepDuration {
+    /// The total time spent in the step, including its children.
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
+    pub total_duration: SignedDuration,
+    /// The time spent in the step itself, excluding its children.
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
+    pub self_duration: SignedDuration,
+    /// The number of times the step was entered.
+    pub calls: u64,
+    /// The shortest total duration of a single call.
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
+    pub min_duration: SignedDuration,
+    /// The longest total duration of a single call.
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
+    pub max_duration: SignedDuration,
+    /// The average total duration of a single call.
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
+    pub mean_duration: SignedDuration,
+    /// Whether the step was running when the progress was cancelled. Only serialized when `true`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
+    /// The state of the step if it never moved while it was on the stack and was always in the same state.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<StepState>,
+}
+
+impl StepDuration {
+    fn from_occurrence(occurrence: &StepOccurrence) -> Self {
+        Self {
+            total_duration: occurrence.total_duration,
+            self_duration: occurrence.self_duration,
+            calls: 1,
+            min_duration: occurrence.total_duration,
+            max_duration: occurrence.total_duration,
+            mean_duration: occurrence.total_duration,
+            cancelled: occurrence.cancelled,
+            state: occurrence.state,
+        }
+    }
+
+    fn add_occurrence(&mut self, occurrence: &StepOccurrence) {
+        self.total_duration += occurrence.total_duration;
+        self.self_duration += occurrence.self_duration;
+        self.calls += 1;
+        self.min_duration = self.min_duration.min(occurrence.total_duration);
+        self.max_duration = self.max_duration.max(occurrence.total_duration);
+        self.mean_duration =
+            SignedDuration::from_nanos_i128(self.total_duration.as_nanos() / self.calls as i128);
+        self.cancelled |= occurrence.cancelled;
+        if self.state != occurrence.state {
+            self.state = None;
+        }
+    }
+}
+
+/// The durations of a step and of all the steps that were entered while it was running.
+///
+/// Contrary to [`StepDuration`] the names aren't joined together which means
+/// a step name can contain anything, including `" > "`.
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DurationTree {
+    pub name: Cow<'static, str>,
+    /// The total time spent in the step, including its children.
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
+    pub total_duration: SignedDuration,
+    /// The time spent in the step itself, excluding its children.
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
+    pub self_duration: SignedDuration,
+    /// The number of times the step was entered.
+    pub calls: u64,
+    /// Whether the step was running when the progress was cancelled. Only serialized when `true`.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
+    /// The steps entered while this one was running, in the order they were first seen.
+    pub children: Vec<DurationTree>,
+}
+
+impl DurationTree {
+    fn new(name: Cow<'static, str>) -> Self {
+        Self {
+            name,
+            total_duration: SignedDuration::ZERO,
+            self_duration: SignedDuration::ZERO,
+            calls: 0,
+            cancelled: false,
+            children: Vec::new(),
+        }
+    }
+
+    #[allow(clippy::ptr_arg)] // We need to clone the `Cow` to keep borrowed names borrowed.
+    fn child(children: &mut Vec<DurationTree>, name: &Cow<'static, str>) -> usize {
+        match children.iter().position(|child| child.name == *name) {
+            Some(idx) => idx,
+            None => {
+                children.push(DurationTree::new(name.clone()));
+                children.len() - 1
+            }
+        }
+    }
+}
+
+/// Build the forest of steps from their occurrences.
+pub(crate) fn build_tree<'a>(
+    occurrences: impl IntoIterator<Item = &'a StepOccurrence>,
+) -> Vec<DurationTree> {
+    let mut roots = Vec::new();
+    for occurrence in occurrences {
+        let Some((first, rest)) = occurrence.path.split_first() else {
+            continue;
+        };
+        let idx = DurationTree::child(&mut roots, first);
+        let mut node = &mut roots[idx];
+        for name in rest {
+            let idx = DurationTree::child(&mut node.children, name);
+            node = &mut node.children[idx];
+        }
+        node.total_duration += occurrence.total_duration;
+        node.self_duration += occurrence.self_duration;
+        node.calls += 1;
+        node.cancelled |= occurrence.cancelled;
+    }
+    roots
+}
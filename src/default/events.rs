@@ -0,0 +1,104 @@
+use std::{borrow::Cow, sync::Arc};
+
+use jiff::SignedDuration;
+
+use super::{DefaultProgress, StepOccurrence};
+
+/// Something that happened to a [`DefaultProgress`], see [`DefaultProgress::subscribe`].
+#[derive(Debug, Clone)]
+pub enum ProgressEvent {
+    /// A step was pushed on the stack.
+    StepEntered {
+        /// The names of the step and all its parents, starting from the root.
+        path: Vec<Cow<'static, str>>,
+        current: u64,
+        total: u64,
+    },
+    /// A step left the stack because it was replaced or the progress finished.
+    StepExited {
+        /// The names of the step and all its parents, starting from the root.
+        path: Vec<Cow<'static, str>>,
+        total_duration: SignedDuration,
+        self_duration: SignedDuration,
+    },
+    /// The progress was cancelled, see [`DefaultProgress::cancel`].
+    Cancelled {
+        /// The time elapsed since the progress was created.
+        duration: SignedDuration,
+    },
+    /// The progress finished.
+    Finished {
+        /// The time elapsed since the progress was created.
+        duration: SignedDuration,
+    },
+}
+
+impl ProgressEvent {
+    pub(crate) fn exited(occurrence: &StepOccurrence) -> Self {
+        ProgressEvent::StepExited {
+            path: occurrence.path.clone(),
+            total_duration: occurrence.total_duration,
+            self_duration: occurrence.self_duration,
+        }
+    }
+}
+
+/// Identifies a listener registered with [`DefaultProgress::subscribe`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ListenerId(u64);
+
+pub(crate) type Listener = Arc<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+#[derive(Default)]
+pub(crate) struct Listeners {
+    next_id: u64,
+    listeners: Vec<(ListenerId, Listener)>,
+}
+
+impl Listeners {
+    pub fn is_empty(&self) -> bool {
+        self.listeners.is_empty()
+    }
+
+    /// Clone the listeners so they can be called once the lock on the progress is released.
+    pub fn to_vec(&self) -> Vec<Listener> {
+        self.listeners
+            .iter()
+            .map(|(_, listener)| listener.clone())
+            .collect()
+    }
+}
+
+/// Send the events to every listener, in order.
+pub(crate) fn notify(listeners: &[Listener], events: &[ProgressEvent]) {
+    for event in events {
+        for listener in listeners {
+            listener(event);
+        }
+    }
+}
+
+impl DefaultProgress {
+    /// Register a function called every time a step enters or exits the stack and when the progress is cancelled or finishes.
+    ///
+    /// The listener is called on the thread that updated the progress, once the lock on
+    /// the progress is released, which means it can safely use the progress.
+    /// But if multiple threads update the progress at the same time the events may be received out of order.
+    pub fn subscribe(
+        &self,
+        listener: impl Fn(&ProgressEvent) + Send + Sync + 'static,
+    ) -> ListenerId {
+        let mut inner = self.steps.write().unwrap();
+        let Listeners { next_id, listeners } = &mut inner.listeners;
+        let id = ListenerId(*next_id);
+        *next_id += 1;
+        listeners.push((id, Arc::new(listener)));
+        id
+    }
+
+    /// Stop sending events to a listener. Does nothing if it was already unsubscribed.
+    pub fn unsubscribe(&self, id: ListenerId) {
+        let mut inner = self.steps.write().unwrap();
+        inner.listeners.listeners.retain(|(other, _)| *other != id);
+    }
+}
@@ -0,0 +1,118 @@
+use std::any::TypeId;
+
+use super::{
+    BranchId, DefaultProgress, InnerProgress, ProgressBranch, ProgressEvent, StepId, events,
+    find_branch, pop_steps,
+};
+use crate::Step;
+
+/// A step that leaves the stack as soon as the guard is dropped, see [`DefaultProgress::enter`].
+#[must_use = "the step leaves the stack as soon as the guard is dropped"]
+pub struct StepGuard {
+    progress: DefaultProgress,
+    branch: Option<BranchId>,
+    /// `None` if the step was never pushed because its branch was already finished.
+    id: Option<StepId>,
+}
+
+impl DefaultProgress {
+    /// Push a step on the stack until the returned guard is dropped.
+    ///
+    /// Contrary to [`DefaultProgress::update`], the step and all its children leave the stack,
+    /// and their durations are recorded, as soon as the guard is dropped, including when unwinding after a panic.
+    /// If the step was already replaced by another step of the same type, dropping the guard does nothing.
+    /// ```
+    /// # use steppe::make_enum_progress;
+    /// # use steppe::default::DefaultProgress;
+    /// make_enum_progress! {
+    ///     pub enum IndexingSteps {
+    ///         Extracting,
+    ///         Writing,
+    ///     }
+    /// }
+    ///
+    /// let progress = DefaultProgress::default();
+    /// {
+    ///     let _guard = progress.enter(IndexingSteps::Extracting);
+    ///     // ... extract the documents ...
+    /// }
+    /// // The extraction is not running anymore
+    /// assert!(progress.as_progress_view().steps.is_empty());
+    /// ```
+    pub fn enter<P: Step>(&self, step: P) -> StepGuard {
+        StepGuard {
+            progress: self.clone(),
+            branch: None,
+            id: self.push_step(None, Box::new(step), TypeId::of::<P>()),
+        }
+    }
+
+    /// Pop the step and all its children if it's still in the stack of the branch.
+    fn pop_step(&self, branch: Option<BranchId>, id: StepId) {
+        // Don't panic again if we're unwinding after a panic that poisoned the lock.
+        let Ok(mut inner) = self.steps.write() else {
+            return;
+        };
+        let InnerProgress {
+            steps,
+            branches,
+            durations,
+            timeline,
+            listeners,
+            ..
+        } = &mut *inner;
+
+        let (steps, prefix) = match branch {
+            None => (steps, &[][..]),
+            Some(branch) => match find_branch(branches, steps, branch) {
+                Some((siblings, idx)) => {
+                    let branch = &mut siblings[idx];
+                    (&mut branch.steps, &branch.path[..])
+                }
+                None => return,
+            },
+        };
+        let Some(idx) = steps.iter().position(|step| step.id == id) else {
+            return;
+        };
+
+        let now = jiff::Timestamp::now();
+        let popped = durations.len();
+        pop_steps(prefix, steps, durations, timeline, now, idx);
+
+        let events: Vec<_> = durations[popped..]
+            .iter()
+            .map(ProgressEvent::exited)
+            .collect();
+        let listeners = listeners.to_vec();
+        drop(inner);
+        self.changes.notify();
+        events::notify(&listeners, &events);
+    }
+}
+
+impl ProgressBranch {
+    /// Push a step on the stack of the branch until the returned guard is dropped, see [`DefaultProgress::enter`].
+    pub fn enter<P: Step>(&self, step: P) -> StepGuard {
+        StepGuard {
+            progress: self.progress.clone(),
+            branch: Some(self.id),
+            id: self
+                .progress
+                .push_step(Some(self.id), Box::new(step), TypeId::of::<P>()),
+        }
+    }
+}
+
+impl StepGuard {
+    /// Pop the step right away. Same as dropping the guard.
+    pub fn exit(self) {}
+}
+
+impl Drop for StepGuard {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.progress.pop_step(self.branch, id);
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::{thread::JoinHandle, time::Duration};
+
+use ::indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+use super::{DefaultProgress, ProgressStepView, ProgressView, view::format_duration};
+
+/// The resolution of the bar of the whole progress, in permille.
+const GLOBAL_LENGTH: u64 = 1000;
+
+/// The bars drawn for a progress: one for the whole progress and one for every level of the stack of steps.
+struct Bars {
+    multi: MultiProgress,
+    global: ProgressBar,
+    steps: Vec<StepBar>,
+}
+
+struct StepBar {
+    bar: ProgressBar,
+    /// Whether the bar is styled for a step whose total is known.
+    determinate: bool,
+}
+
+impl Bars {
+    fn new(multi: MultiProgress) -> Self {
+        let global = multi.add(
+            ProgressBar::new(GLOBAL_LENGTH).with_style(
+                ProgressStyle::with_template("[{elapsed_precise}] {wide_bar:.cyan/blue} {msg}")
+                    .unwrap()
+                    .progress_chars("##-"),
+            ),
+        );
+        Self {
+            multi,
+            global,
+            steps: Vec::new(),
+        }
+    }
+
+    fn draw(&mut self, view: &ProgressView) {
+        self.global
+            .set_position((view.percentage / 100.0 * GLOBAL_LENGTH as f32) as u64);
+        self.global.set_message(match view.eta {
+            Some(eta) => format!("{:.1}% (eta {})", view.percentage, format_duration(eta)),
+            None => format!("{:.1}%", view.percentage),
+        });
+
+        // The bars of the steps that left the stack are removed.
+        for step in self.steps.drain(view.steps.len().min(self.steps.len())..) {
+            step.bar.finish_and_clear();
+            self.multi.remove(&step.bar);
+        }
+        for (depth, step) in view.steps.iter().enumerate() {
+            if depth < self.steps.len() {
+                self.steps[depth].update(step);
+                continue;
+            }
+            // The bar is set up before being added so it's never drawn with the default style.
+            let bar = ProgressBar::hidden().with_prefix("  ".repeat(depth + 1));
+            let mut bar = StepBar::new(bar, step);
+            bar.update(step);
+            self.multi.add(bar.bar.clone());
+            self.steps.push(bar);
+        }
+    }
+
+    fn finish(self) {
+        for step in self.steps {
+            step.bar.finish_and_clear();
+        }
+        self.global.finish_and_clear();
+    }
+}
+
+impl StepBar {
+    fn new(bar: ProgressBar, step: &ProgressStepView) -> Self {
+        let determinate = step.total.is_some();
+        bar.set_style(Self::style(determinate));
+        Self { bar, determinate }
+    }
+
+    fn style(determinate: bool) -> ProgressStyle {
+        let template = if determinate {
+            "{prefix}{msg} {bar:30.green/white} {pos}/{len}"
+        } else {
+            "{prefix}{msg} {spinner} {pos}"
+        };
+        ProgressStyle::with_template(template)
+            .unwrap()
+            .progress_chars("=> ")
+    }
+
+    fn update(&mut self, step: &ProgressStepView) {
+        if self.determinate != step.total.is_some() {
+            self.determinate = step.total.is_some();
+            self.bar.set_style(Self::style(self.determinate));
+        }
+        match step.total {
+            Some(total) => self.bar.set_length(total),
+            None => {
+                self.bar.unset_length();
+                self.bar.tick();
+            }
+        }
+        self.bar.set_position(step.finished);
+        self.bar.set_message(match &step.throughput {
+            Some(throughput) => format!("{} ({throughput})", step.current_step),
+            None => step.current_step.to_string(),
+        });
+    }
+}
+
+impl DefaultProgress {
+    /// Follow the progression with [`indicatif`] progress bars drawn in the given [`MultiProgress`].
+    ///
+    /// Starts a new thread that:
+    /// - Draws a bar for the whole progress and a nested bar for every level of the stack of steps,
+    ///   refreshed every 100ms or as soon as a step changes.
+    /// - Clears the bars and prints the accumulated durations of each steps once the progress is finished.
+    ///
+    /// Use [`MultiProgress::println`] to print other stuff without breaking the bars.
+    /// The returned handle can be joined to wait for the durations to be printed.
+    pub fn follow_progression_with_indicatif(&self, multi: MultiProgress) -> JoinHandle<()> {
+        let this = self.clone();
+        std::thread::spawn(move || {
+            let mut bars = Bars::new(multi.clone());
+            loop {
+                let finished = this.is_finished();
+                bars.draw(&this.as_progress_view());
+                if finished {
+                    break;
+                }
+                this.wait_for_change(Duration::from_millis(100));
+            }
+            bars.finish();
+            // indicatif adds its own newline.
+            let table = this.durations_table(true);
+            let _ = multi.println(table.trim_end());
+        })
+    }
+}
@@ -1,12 +1,46 @@
+mod branch;
+mod diff;
+mod durations;
+mod events;
+mod guard;
+#[cfg(feature = "indicatif")]
+mod indicatif;
+mod profile;
+mod rate;
+mod snapshot;
+#[cfg(feature = "async")]
+mod stream;
+mod trace;
+mod tty;
 mod view;
+mod wait;
 
 use std::{
     any::TypeId,
-    sync::{Arc, RwLock},
+    borrow::Cow,
+    sync::{
+        Arc, Mutex, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
 };
 
 use crate::{Progress, Step};
-pub use view::{ProgressStepView, ProgressView, StepDuration};
+pub use branch::ProgressBranch;
+use branch::{Branch, BranchId, find_branch};
+pub use diff::{DurationDelta, ProfileDiff, StepDiff};
+use durations::StepOccurrence;
+pub use durations::{DurationTree, StepDuration, StepState};
+use events::Listeners;
+pub use events::{ListenerId, ProgressEvent};
+pub use guard::StepGuard;
+use profile::LearnedWeights;
+use rate::RateEstimator;
+pub use snapshot::{BranchSnapshot, OccurrenceSnapshot, ProgressSnapshot, StepSnapshot};
+use trace::Timeline;
+pub use trace::{ChromeTrace, ChromeTraceArgs, ChromeTraceEvent};
+pub use tty::{TtyFollower, TtyFollowerHandle};
+pub use view::{ProgressBranchView, ProgressStepView, ProgressView, Throughput};
+use wait::Changes;
 
 /// The main struct of the crate.
 /// It stores the current steps we're processing.
@@ -19,19 +53,42 @@ pub use view::{ProgressStepView, ProgressView, StepDuration};
 #[derive(Clone, Default)]
 pub struct DefaultProgress {
     steps: Arc<RwLock<InnerProgress>>,
+    /// Used to wake up the threads waiting for the progress to change.
+    changes: Arc<Changes>,
 }
 
 impl Progress for DefaultProgress {
     fn update(&self, sub_progress: impl Step) {
         self.update(sub_progress);
     }
+
+    fn update_boxed(&self, sub_progress: Box<dyn Step>, type_id: TypeId) {
+        self.update_boxed(sub_progress, type_id);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.is_cancelled()
+    }
 }
 
 struct InnerProgress {
     /// The hierarchy of steps.
     steps: Vec<InnerStep>,
-    /// The durations associated to each steps.
-    durations: Vec<(String, jiff::SignedDuration, jiff::SignedDuration)>,
+    /// The branches forked while no step was running.
+    branches: Vec<Branch>,
+    /// The number of branches attached to the progress itself that finished while others are still running.
+    /// They count as done in the percentage until all the branches are finished.
+    finished_branches: usize,
+    /// Every occurrence of the steps that left the stack.
+    durations: Vec<StepOccurrence>,
+    /// Every step that entered or exited the stack.
+    timeline: Timeline,
+    /// The weights of the steps learned from a previous run.
+    learned_weights: LearnedWeights,
+    /// The functions to call when a step enters or exits the stack.
+    listeners: Listeners,
+    /// The time at which the progress was cancelled.
+    cancelled_at: Option<jiff::Timestamp>,
     /// The time at which the progress was finished.
     finished_at: Option<jiff::Timestamp>,
     /// The time at which the progress was created.
@@ -39,17 +96,48 @@ struct InnerProgress {
 }
 
 struct InnerStep {
+    /// Identifies this occurrence of the step, even among the steps of the same type.
+    id: StepId,
     type_id: TypeId,
     step: Box<dyn Step>,
     started_at: jiff::Timestamp,
+    /// The `current` of the step when it entered the stack.
+    initial_current: u64,
+    /// The id of the thread that entered the step in the timeline.
+    thread: u64,
+    /// The total duration of the children that already left the stack.
     time_spent_in_children: jiff::SignedDuration,
+    /// Sampled every time the view is generated.
+    rate: Mutex<RateEstimator>,
+    /// Whether the step was running when the progress was cancelled.
+    cancelled: bool,
+    /// The branches forked while the step was the deepest one.
+    branches: Vec<Branch>,
+}
+
+/// Used to give a unique id to every step pushed on a stack.
+static NEXT_STEP_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct StepId(u64);
+
+impl StepId {
+    fn new() -> Self {
+        StepId(NEXT_STEP_ID.fetch_add(1, Ordering::Relaxed))
+    }
 }
 
 impl Default for InnerProgress {
     fn default() -> Self {
         Self {
             steps: vec![],
+            branches: vec![],
+            finished_branches: 0,
             durations: vec![],
+            timeline: Timeline::default(),
+            learned_weights: LearnedWeights::default(),
+            listeners: Listeners::default(),
+            cancelled_at: None,
             finished_at: None,
             start_time: jiff::Timestamp::now(),
         }
@@ -64,27 +152,91 @@ impl DefaultProgress {
     ///
     /// If the step is found and the current is higher than the total, it will be ignored.
     pub fn update<P: Step>(&self, sub_progress: P) {
+        self.update_boxed(Box::new(sub_progress), TypeId::of::<P>());
+    }
+
+    /// Same as [`DefaultProgress::update`] with a step whose type has been erased,
+    /// `step_type` must be the [`TypeId`] of the original step.
+    pub fn update_boxed(&self, sub_progress: Box<dyn Step>, step_type: TypeId) {
+        self.push_step(None, sub_progress, step_type);
+    }
+
+    /// Push the step on the main stack or on the stack of a branch.
+    /// Returns the id of the step, or `None` if the branch is finished.
+    fn push_step(
+        &self,
+        branch: Option<BranchId>,
+        sub_progress: Box<dyn Step>,
+        step_type: TypeId,
+    ) -> Option<StepId> {
         let mut inner = self.steps.write().unwrap();
         let InnerProgress {
             steps,
+            branches,
+            finished_branches: _,
             durations,
+            timeline,
+            learned_weights: _,
+            listeners,
+            cancelled_at: _,
             finished_at: _,
             start_time: _,
         } = &mut *inner;
 
+        let (steps, prefix) = match branch {
+            None => (steps, &[][..]),
+            Some(id) => match find_branch(branches, steps, id) {
+                Some((siblings, idx)) if !siblings[idx].finished => {
+                    let Branch { steps, path, .. } = &mut siblings[idx];
+                    (steps, &path[..])
+                }
+                // The branch is finished, there is nothing to update.
+                _ => return None,
+            },
+        };
+
         let now = jiff::Timestamp::now();
-        let step_type = TypeId::of::<P>();
+        let popped = durations.len();
         if let Some(idx) = steps.iter().position(|step| step.type_id == step_type) {
-            push_steps_durations(steps, durations, now, idx);
-            steps.truncate(idx);
+            pop_steps(prefix, steps, durations, timeline, now, idx);
+        }
+        // Don't pay for the events if no one is listening.
+        let mut events = Vec::new();
+        if !listeners.is_empty() {
+            events.extend(durations[popped..].iter().map(ProgressEvent::exited));
+            events.push(ProgressEvent::StepEntered {
+                path: prefix
+                    .iter()
+                    .cloned()
+                    .chain(steps.iter().map(|step| step.step.name()))
+                    .chain(Some(sub_progress.name()))
+                    .collect(),
+                current: sub_progress.current(),
+                total: sub_progress.total(),
+            });
         }
 
+        let thread = timeline.enter(&*sub_progress, now);
+        let rate = Mutex::new(RateEstimator::new(now, sub_progress.current()));
+        let id = StepId::new();
         steps.push(InnerStep {
+            id,
             type_id: step_type,
-            step: Box::new(sub_progress),
+            initial_current: sub_progress.current(),
+            step: sub_progress,
             started_at: now,
+            thread,
             time_spent_in_children: jiff::SignedDuration::ZERO,
+            rate,
+            cancelled: false,
+            branches: Vec::new(),
         });
+
+        let listeners = listeners.to_vec();
+        drop(inner);
+        self.changes.notify();
+        events::notify(&listeners, &events);
+        Some(id)
     }
 
     /// Drop all the steps and update the durations.
@@ -95,9 +247,15 @@ impl DefaultProgress {
         let mut inner = self.steps.write().unwrap();
         let InnerProgress {
             steps,
+            branches,
+            finished_branches,
             durations,
+            timeline,
+            learned_weights: _,
+            listeners,
+            cancelled_at: _,
             finished_at,
-            start_time: _,
+            start_time,
         } = &mut *inner;
 
         if finished_at.is_some() {
@@ -106,38 +264,166 @@ impl DefaultProgress {
 
         let now = jiff::Timestamp::now();
         *finished_at = Some(now);
-        push_steps_durations(steps, durations, now, 0);
-        steps.clear();
+        let popped = durations.len();
+        pop_steps(&[], steps, durations, timeline, now, 0);
+        for Branch { path, steps, .. } in branches.iter_mut() {
+            pop_steps(path, steps, durations, timeline, now, 0);
+        }
+        branches.clear();
+        *finished_branches = 0;
+
+        let events: Vec<_> = durations[popped..]
+            .iter()
+            .map(ProgressEvent::exited)
+            .chain(Some(ProgressEvent::Finished {
+                duration: now.duration_since(*start_time),
+            }))
+            .collect();
+        let listeners = listeners.to_vec();
+        drop(inner);
+        self.changes.notify();
+        events::notify(&listeners, &events);
     }
 
     pub fn is_finished(&self) -> bool {
         let inner = self.steps.read().unwrap();
         inner.finished_at.is_some()
     }
+
+    /// Ask the task to stop, see [`Progress::is_cancelled`].
+    ///
+    /// The progress keeps working as usual: the task is free to go through some more steps to clean up
+    /// before calling [`DefaultProgress::finish`]. The steps that were running are flagged as cancelled
+    /// in the durations. Does nothing if the progress is already cancelled or finished.
+    pub fn cancel(&self) {
+        let mut inner = self.steps.write().unwrap();
+        let InnerProgress {
+            steps,
+            branches,
+            listeners,
+            cancelled_at,
+            finished_at,
+            start_time,
+            ..
+        } = &mut *inner;
+
+        if cancelled_at.is_some() || finished_at.is_some() {
+            return;
+        }
+
+        let now = jiff::Timestamp::now();
+        *cancelled_at = Some(now);
+        cancel_steps(steps);
+        for branch in branches.iter_mut() {
+            cancel_steps(&mut branch.steps);
+        }
+
+        let events = [ProgressEvent::Cancelled {
+            duration: now.duration_since(*start_time),
+        }];
+        let listeners = listeners.to_vec();
+        drop(inner);
+        self.changes.notify();
+        events::notify(&listeners, &events);
+    }
+
+    /// Returns `true` once [`DefaultProgress::cancel`] has been called.
+    pub fn is_cancelled(&self) -> bool {
+        let inner = self.steps.read().unwrap();
+        inner.cancelled_at.is_some()
+    }
+}
+
+/// Flag the steps and the steps of their branches as cancelled.
+fn cancel_steps(steps: &mut [InnerStep]) {
+    for step in steps {
+        step.cancelled = true;
+        for branch in &mut step.branches {
+            cancel_steps(&mut branch.steps);
+        }
+    }
+}
+
+/// Call `f` on the steps and the steps of their branches, from the deepest to the shallowest.
+fn for_each_step_rev<'a>(steps: &'a [InnerStep], f: &mut impl FnMut(&'a InnerStep)) {
+    for step in steps.iter().rev() {
+        for branch in &step.branches {
+            for_each_step_rev(&branch.steps, f);
+        }
+        f(step);
+    }
 }
 
-/// Generate the names associated with the durations and push them.
-fn push_steps_durations(
+/// Generate the occurrences of all the steps still running, including the ones of the branches.
+fn running_durations(inner: &InnerProgress, now: jiff::Timestamp) -> Vec<StepOccurrence> {
+    let mut occurrences = steps_durations(&[], &inner.steps, now, 0);
+    for branch in &inner.branches {
+        occurrences.extend(steps_durations(&branch.path, &branch.steps, now, 0));
+    }
+    occurrences
+}
+
+/// Generate the occurrences of the steps starting at `idx`, from the deepest step to the shallowest.
+/// The occurrences of the steps of their branches come right before them. The `prefix` contains
+/// the names of the parents of the first step.
+///
+/// The self duration of a step is its total duration minus the time spent in the children
+/// that already left the stack and the time spent in the child that is still running.
+/// The time spent in the branches is not subtracted since they run concurrently.
+fn steps_durations(
+    prefix: &[Cow<'static, str>],
     steps: &[InnerStep],
-    durations: &mut Vec<(String, jiff::SignedDuration, jiff::SignedDuration)>,
     now: jiff::Timestamp,
     idx: usize,
-) {
-    let mut father_duration: Option<jiff::SignedDuration> = None;
+) -> Vec<StepOccurrence> {
+    let mut occurrences = Vec::with_capacity(steps.len().saturating_sub(idx));
+    let mut child_duration = jiff::SignedDuration::ZERO;
 
-    for (i, step) in steps.iter().skip(idx).enumerate().rev() {
-        let full_name = steps
+    for (i, step) in steps.iter().enumerate().skip(idx).rev() {
+        for branch in &step.branches {
+            occurrences.extend(steps_durations(&branch.path, &branch.steps, now, 0));
+        }
+        let path = prefix
             .iter()
-            .take(idx + i + 1)
-            .map(|step| step.step.name())
-            .collect::<Vec<_>>()
-            .join(" > ");
+            .cloned()
+            .chain(steps[..=i].iter().map(|step| step.step.name()))
+            .collect();
         let total_duration = now.duration_since(step.started_at);
-        let self_duration = match father_duration {
-            Some(father) => total_duration - father,
-            None => total_duration,
-        };
-        durations.push((full_name, total_duration, self_duration));
-        father_duration = Some(total_duration);
+        let self_duration = total_duration - step.time_spent_in_children - child_duration;
+        let current = step.step.current();
+        occurrences.push(StepOccurrence {
+            path,
+            total_duration,
+            self_duration,
+            cancelled: step.cancelled,
+            state: (current == step.initial_current).then(|| StepState {
+                current,
+                total: step.step.total(),
+            }),
+        });
+        child_duration = total_duration;
+    }
+
+    occurrences
+}
+
+/// Pop all the steps starting at `idx` with their branches, record their durations and give
+/// the time they took to their parent.
+fn pop_steps(
+    prefix: &[Cow<'static, str>],
+    steps: &mut Vec<InnerStep>,
+    durations: &mut Vec<StepOccurrence>,
+    timeline: &mut Timeline,
+    now: jiff::Timestamp,
+    idx: usize,
+) {
+    for_each_step_rev(&steps[idx..], &mut |step| {
+        timeline.exit(&*step.step, step.thread, now)
+    });
+    let occurrences = steps_durations(prefix, steps, now, idx);
+    if let (Some(parent), Some(popped)) = (idx.checked_sub(1), occurrences.last()) {
+        steps[parent].time_spent_in_children += popped.total_duration;
     }
+    durations.extend(occurrences);
+    steps.truncate(idx);
 }
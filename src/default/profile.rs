@@ -0,0 +1,116 @@
+use std::io;
+
+use indexmap::IndexMap;
+
+use super::{DefaultProgress, StepDuration, StepState};
+
+/// The durations of the steps of a previous run, grouped by parent.
+///
+/// They're used as weights when computing the global percentage: a step that took 90% of
+/// the time of its parent last time is expected to take 90% of it again.
+#[derive(Default)]
+pub(crate) struct LearnedWeights {
+    /// The full name of the parent (empty for the root steps) to the name of its children, their total duration
+    /// in seconds and the state they were in if they never moved.
+    /// The children are in the order they were first seen in the previous run.
+    children: IndexMap<String, IndexMap<String, (f32, Option<StepState>)>>,
+}
+
+impl LearnedWeights {
+    pub fn new(profile: &IndexMap<String, StepDuration>) -> Self {
+        let mut children: IndexMap<String, IndexMap<String, (f32, Option<StepState>)>> =
+            IndexMap::new();
+        for (path, duration) in profile {
+            let (parent, name) = path.rsplit_once(" > ").unwrap_or(("", path));
+            children
+                .entry(parent.to_string())
+                .or_default()
+                .entry(name.to_string())
+                .and_modify(|(weight, state)| {
+                    *weight += duration.total_duration.as_secs_f32();
+                    if *state != duration.state {
+                        *state = None;
+                    }
+                })
+                .or_insert((duration.total_duration.as_secs_f32(), duration.state));
+        }
+        Self { children }
+    }
+
+    /// Returns the weight of what is already done, the weight of the current state of the step,
+    /// and the sum of all the weights, the weights being the time spent in the siblings during the previous run.
+    ///
+    /// When the step was in the same state last time, e.g. it's a variant of an enum, its siblings in the
+    /// other states of the same step are done or to come depending on their state, and a state that wasn't
+    /// seen last time has no weight. Otherwise the step moved by itself and its share is split evenly between its states.
+    ///
+    /// Only returns something if the step had siblings last time, otherwise its weight
+    /// is meaningless and the progress of the step must be computed as usual.
+    pub fn weights(
+        &self,
+        parent: &str,
+        name: &str,
+        current: u64,
+        total: u64,
+    ) -> Option<(f32, f32, f32)> {
+        let siblings = self.children.get(parent)?;
+        if siblings.len() < 2 {
+            return None;
+        }
+        let sum: f32 = siblings.values().map(|(weight, _)| weight).sum();
+        if sum <= 0.0 {
+            return None;
+        }
+
+        let state = Some(StepState { current, total });
+        let weight = match siblings.get_full(name) {
+            Some((_, _, (weight, sibling_state))) if *sibling_state == state => *weight,
+            Some((idx, _, (weight, _))) => {
+                // The share of the step is split between its own states.
+                let done: f32 = siblings.values().take(idx).map(|(weight, _)| weight).sum();
+                let total = total as f32;
+                return Some((done * total + weight * current as f32, *weight, sum * total));
+            }
+            None if siblings
+                .values()
+                .any(|(_, sibling_state)| sibling_state.is_some_and(|s| s.total == total)) =>
+            {
+                0.0
+            }
+            None => return None,
+        };
+        // The name of the step tells us which of its states we're in.
+        let done = siblings
+            .values()
+            .filter(|(_, sibling_state)| {
+                sibling_state.is_some_and(|s| s.total == total && s.current < current)
+            })
+            .map(|(weight, _)| weight)
+            .sum();
+        Some((done, weight, sum))
+    }
+}
+
+impl DefaultProgress {
+    /// Create a progress that uses the durations of a previous run, as returned by
+    /// [`DefaultProgress::accumulated_durations`], to weight the steps.
+    ///
+    /// The global percentage and the ETA get accurate from the second run on, as long as
+    /// the steps take roughly the same share of the time.
+    /// The profile records the `current` and `total` of the steps that didn't move while they were on the stack,
+    /// so a step whose name is one of its states, e.g. an enum, counts the siblings in its previous states as done,
+    /// while a step moving by itself, e.g. a [`crate::AtomicSubStep`] or [`crate::VariableNameStep`], still moves inside its share.
+    /// The steps that weren't seen during the previous run are weighted as usual, except for the unseen states
+    /// of a step, which had no weight last time.
+    pub fn with_profile(profile: &IndexMap<String, StepDuration>) -> Self {
+        let progress = Self::default();
+        progress.steps.write().unwrap().learned_weights = LearnedWeights::new(profile);
+        progress
+    }
+
+    /// Same as [`DefaultProgress::with_profile`] but reads the profile as JSON.
+    pub fn with_json_profile(reader: impl io::Read) -> serde_json::Result<Self> {
+        let profile: IndexMap<String, StepDuration> = serde_json::from_reader(reader)?;
+        Ok(Self::with_profile(&profile))
+    }
+}
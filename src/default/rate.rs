@@ -0,0 +1,81 @@
+use jiff::{SignedDuration, Timestamp};
+
+/// How fast the smoothed rate forgets the old samples.
+/// After this duration a sample only weights for ~37% of the rate.
+const SMOOTHING_WINDOW: SignedDuration = SignedDuration::from_secs(5);
+/// The minimum duration between two samples. The views generated in between all report the same rates.
+const SAMPLING_INTERVAL: SignedDuration = SignedDuration::from_secs(1);
+
+/// Estimates the number of states a step goes through per second by sampling its `current` value.
+///
+/// Until the step moves, no rate is reported. The first rate is the average since the step was
+/// entered, then it's smoothed exponentially with a weight depending on the time elapsed
+/// between two samples. A new sample is only registered once per [`SAMPLING_INTERVAL`] so
+/// it doesn't matter how often and by how many consumers the view is requested.
+pub(crate) struct RateEstimator {
+    last_sample: (Timestamp, u64),
+    rate: Option<f64>,
+    /// The rate between the two last samples, without any smoothing.
+    instantaneous: f64,
+}
+
+impl RateEstimator {
+    pub fn new(started_at: Timestamp, current: u64) -> Self {
+        Self {
+            last_sample: (started_at, current),
+            rate: None,
+            instantaneous: 0.0,
+        }
+    }
+
+    /// Register the current value of the step if the last sample is old enough and returns
+    /// the smoothed rate per second and the rate between the two last samples.
+    pub fn sample(&mut self, now: Timestamp, current: u64) -> (Option<f64>, f64) {
+        let (last_time, last_current) = self.last_sample;
+        let elapsed = now.duration_since(last_time);
+        let instantaneous = match elapsed.as_secs_f64() {
+            // Another view may have registered a sample after we retrieved the time.
+            elapsed if elapsed <= 0.0 => 0.0,
+            elapsed => current.saturating_sub(last_current) as f64 / elapsed,
+        };
+        if elapsed < SAMPLING_INTERVAL {
+            return match self.rate {
+                // Until the first sample is registered the rate is the average since the step was entered.
+                None if current != last_current => (Some(instantaneous), instantaneous),
+                rate => (rate, self.instantaneous),
+            };
+        }
+
+        let elapsed = elapsed.as_secs_f64();
+        match self.rate {
+            // The step never moved, we keep the first sample to compute the average.
+            None if current == last_current => return (None, 0.0),
+            None => self.rate = Some(instantaneous),
+            Some(rate) => {
+                let alpha = 1.0 - (-elapsed / SMOOTHING_WINDOW.as_secs_f64()).exp();
+                self.rate = Some(rate + alpha * (instantaneous - rate));
+            }
+        }
+
+        self.instantaneous = instantaneous;
+        self.last_sample = (now, current);
+        (self.rate, self.instantaneous)
+    }
+}
+
+/// Estimate the remaining time linearly from the elapsed time and the percentage of completion.
+pub(crate) fn linear_eta(elapsed: SignedDuration, percentage: f32) -> Option<SignedDuration> {
+    if percentage <= 0.0 || !percentage.is_finite() {
+        return None;
+    }
+    let remaining = (100.0 - percentage.min(100.0)) as f64 / percentage as f64;
+    Some(elapsed.mul_f64(remaining))
+}
+
+/// Estimate the remaining time from the number of states left and the rate per second.
+pub(crate) fn rate_eta(remaining: u64, rate: f64) -> Option<SignedDuration> {
+    if rate <= 0.0 || !rate.is_finite() {
+        return None;
+    }
+    SignedDuration::try_from_secs_f64(remaining as f64 / rate).ok()
+}
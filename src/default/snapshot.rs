@@ -0,0 +1,281 @@
+use std::{any::TypeId, borrow::Cow, io, sync::Mutex};
+
+use jiff::{SignedDuration, Timestamp};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    Branch, BranchId, DefaultProgress, InnerProgress, InnerStep, StepId, StepOccurrence, StepState,
+    Timeline, rate::RateEstimator,
+};
+use crate::Step;
+
+/// The full state of a progress at a given time.
+///
+/// It can be saved to disk with [`DefaultProgress::save_snapshot`] and restored with
+/// [`DefaultProgress::load_snapshot`] to see what a crashed or restarted task was doing,
+/// or to compare the durations of two runs.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressSnapshot {
+    /// The hierarchy of steps that were running.
+    pub steps: Vec<StepSnapshot>,
+    /// The branches that were forked while no step was running.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub branches: Vec<BranchSnapshot>,
+    /// The number of branches forked while no step was running that finished while the others are still running.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub finished_branches: usize,
+    /// Every occurrence of the steps that left the stack, in order.
+    pub durations: Vec<OccurrenceSnapshot>,
+    /// The time at which the progress was created.
+    pub start_time: Timestamp,
+    /// The time at which the progress was cancelled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cancelled_at: Option<Timestamp>,
+    /// The time at which the progress was finished.
+    pub finished_at: Option<Timestamp>,
+    /// The time at which the snapshot was taken.
+    pub taken_at: Timestamp,
+}
+
+/// A step that was running when the snapshot was taken.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct StepSnapshot {
+    pub name: Cow<'static, str>,
+    pub current: u64,
+    pub total: u64,
+    /// The `current` of the step when it entered the stack, to know whether it moved since.
+    #[serde(default)]
+    pub initial_current: u64,
+    pub started_at: Timestamp,
+    /// The total duration of the children that already left the stack.
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
+    pub time_spent_in_children: SignedDuration,
+    /// Whether the step was running when the progress was cancelled.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
+    /// The branches that were forked while the step was the deepest one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub branches: Vec<BranchSnapshot>,
+}
+
+/// A branch that was attached to a step or to the progress when the snapshot was taken, see [`DefaultProgress::fork`].
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BranchSnapshot {
+    pub name: Cow<'static, str>,
+    /// The names of the step the branch is attached to and all its parents.
+    pub path: Vec<Cow<'static, str>>,
+    /// The stack of steps of the branch.
+    pub steps: Vec<StepSnapshot>,
+    /// Whether the branch was already finished. It still counts as done in the percentage of its parent.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub finished: bool,
+}
+
+/// A step that left the stack before the snapshot was taken.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OccurrenceSnapshot {
+    /// The names of the step and all its parents, starting from the root.
+    pub path: Vec<Cow<'static, str>>,
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
+    pub total_duration: SignedDuration,
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
+    pub self_duration: SignedDuration,
+    /// Whether the step was running when the progress was cancelled.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub cancelled: bool,
+    /// The state of the step if it didn't move while it was on the stack.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<StepState>,
+}
+
+/// A step restored from a snapshot, it never moves.
+struct RestoredStep {
+    name: Cow<'static, str>,
+    current: u64,
+    total: u64,
+}
+
+impl Step for RestoredStep {
+    fn name(&self) -> Cow<'static, str> {
+        self.name.clone()
+    }
+
+    fn current(&self) -> u64 {
+        self.current
+    }
+
+    fn total(&self) -> u64 {
+        self.total
+    }
+}
+
+impl DefaultProgress {
+    /// Take a snapshot of the whole state of the progress.
+    pub fn snapshot(&self) -> ProgressSnapshot {
+        let inner = self.steps.read().unwrap();
+        let InnerProgress {
+            steps,
+            branches,
+            finished_branches,
+            durations,
+            cancelled_at,
+            finished_at,
+            start_time,
+            ..
+        } = &*inner;
+
+        ProgressSnapshot {
+            steps: steps_snapshot(steps),
+            branches: branches_snapshot(branches),
+            finished_branches: *finished_branches,
+            durations: durations
+                .iter()
+                .map(|occurrence| OccurrenceSnapshot {
+                    path: occurrence.path.clone(),
+                    total_duration: occurrence.total_duration,
+                    self_duration: occurrence.self_duration,
+                    cancelled: occurrence.cancelled,
+                    state: occurrence.state,
+                })
+                .collect(),
+            start_time: *start_time,
+            cancelled_at: *cancelled_at,
+            finished_at: *finished_at,
+            taken_at: Timestamp::now(),
+        }
+    }
+
+    /// Restore a progress from a snapshot.
+    ///
+    /// The steps that were running are restored with the state they had when the snapshot was taken
+    /// and are kept until [`DefaultProgress::finish`] is called. Since their original type is lost,
+    /// updating the progress with a step of the same type pushes a new step instead of replacing them.
+    /// The branches are restored the same way and are kept until the step they're attached to leaves the stack,
+    /// or until the progress is finished.
+    /// The timeline, once enabled with [`DefaultProgress::with_timeline`], only contains the steps that were running,
+    /// the ones that already left the stack are lost.
+    pub fn from_snapshot(snapshot: ProgressSnapshot) -> Self {
+        let progress = Self::default();
+        {
+            let mut inner = progress.steps.write().unwrap();
+            let InnerProgress {
+                steps,
+                branches,
+                finished_branches,
+                durations,
+                timeline,
+                learned_weights: _,
+                listeners: _,
+                cancelled_at,
+                finished_at,
+                start_time,
+            } = &mut *inner;
+
+            *start_time = snapshot.start_time;
+            *cancelled_at = snapshot.cancelled_at;
+            *finished_at = snapshot.finished_at;
+            durations.extend(
+                snapshot
+                    .durations
+                    .into_iter()
+                    .map(|occurrence| StepOccurrence {
+                        path: occurrence.path,
+                        total_duration: occurrence.total_duration,
+                        self_duration: occurrence.self_duration,
+                        cancelled: occurrence.cancelled,
+                        state: occurrence.state,
+                    }),
+            );
+            *steps = restore_steps(snapshot.steps, timeline);
+            *branches = restore_branches(snapshot.branches, timeline);
+            *finished_branches = snapshot.finished_branches;
+        }
+        progress
+    }
+
+    /// Write a [`DefaultProgress::snapshot`] as JSON in the writer.
+    pub fn save_snapshot(&self, writer: impl io::Write) -> serde_json::Result<()> {
+        serde_json::to_writer(writer, &self.snapshot())
+    }
+
+    /// Restore a progress from a snapshot written by [`DefaultProgress::save_snapshot`].
+    pub fn load_snapshot(reader: impl io::Read) -> serde_json::Result<Self> {
+        let snapshot = serde_json::from_reader(reader)?;
+        Ok(Self::from_snapshot(snapshot))
+    }
+}
+
+fn is_zero(n: &usize) -> bool {
+    *n == 0
+}
+
+fn steps_snapshot(steps: &[InnerStep]) -> Vec<StepSnapshot> {
+    steps
+        .iter()
+        .map(|step| StepSnapshot {
+            name: step.step.name(),
+            current: step.step.current(),
+            total: step.step.total(),
+            initial_current: step.initial_current,
+            started_at: step.started_at,
+            time_spent_in_children: step.time_spent_in_children,
+            cancelled: step.cancelled,
+            branches: branches_snapshot(&step.branches),
+        })
+        .collect()
+}
+
+fn branches_snapshot(branches: &[Branch]) -> Vec<BranchSnapshot> {
+    branches
+        .iter()
+        .map(|branch| BranchSnapshot {
+            name: branch.name.clone(),
+            path: branch.path.clone(),
+            steps: steps_snapshot(&branch.steps),
+            finished: branch.finished,
+        })
+        .collect()
+}
+
+fn restore_steps(steps: Vec<StepSnapshot>, timeline: &mut Timeline) -> Vec<InnerStep> {
+    steps
+        .into_iter()
+        .map(|step| {
+            let restored = RestoredStep {
+                name: step.name,
+                current: step.current,
+                total: step.total,
+            };
+            let thread = timeline.enter(&restored, step.started_at);
+            InnerStep {
+                id: StepId::new(),
+                type_id: TypeId::of::<RestoredStep>(),
+                step: Box::new(restored),
+                started_at: step.started_at,
+                initial_current: step.initial_current,
+                thread,
+                time_spent_in_children: step.time_spent_in_children,
+                rate: Mutex::new(RateEstimator::new(step.started_at, step.current)),
+                cancelled: step.cancelled,
+                branches: restore_branches(step.branches, timeline),
+            }
+        })
+        .collect()
+}
+
+/// No handle exists on the restored branches, the running ones are only closed with the step they're attached to.
+fn restore_branches(branches: Vec<BranchSnapshot>, timeline: &mut Timeline) -> Vec<Branch> {
+    branches
+        .into_iter()
+        .map(|branch| {
+            let mut restored = Branch::new(BranchId::new(), branch.name, branch.path);
+            restored.steps = restore_steps(branch.steps, timeline);
+            restored.finished = branch.finished;
+            restored
+        })
+        .collect()
+}
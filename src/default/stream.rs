@@ -0,0 +1,62 @@
+use std::time::Duration;
+
+use futures_util::{Stream, stream};
+use tokio::sync::watch;
+
+use super::{DefaultProgress, ListenerId, ProgressView};
+
+/// The state of the stream, unsubscribes from the progress when the stream is dropped.
+struct ViewStream {
+    progress: DefaultProgress,
+    listener: ListenerId,
+    changes: watch::Receiver<()>,
+    interval: Duration,
+    first: bool,
+    finished: bool,
+}
+
+impl Drop for ViewStream {
+    fn drop(&mut self) {
+        self.progress.unsubscribe(self.listener);
+    }
+}
+
+impl DefaultProgress {
+    /// Stream the progress view.
+    ///
+    /// A view is emitted right away, then every time a step enters or exits the stack, and at least
+    /// every `interval` to follow the [`crate::AtomicSubStep`] that are updated without notifying anyone.
+    /// The stream ends after emitting the last view once the progress is finished.
+    pub fn view_stream(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = ProgressView> + Send + 'static {
+        let (sender, changes) = watch::channel(());
+        let listener = self.subscribe(move |_event| {
+            sender.send_replace(());
+        });
+        let state = ViewStream {
+            progress: self.clone(),
+            listener,
+            changes,
+            interval,
+            first: true,
+            finished: false,
+        };
+
+        stream::unfold(state, |mut state| async move {
+            if state.finished {
+                return None;
+            }
+            if !state.first {
+                tokio::select! {
+                    _ = state.changes.changed() => (),
+                    _ = tokio::time::sleep(state.interval) => (),
+                }
+            }
+            state.first = false;
+            state.finished = state.progress.is_finished();
+            Some((state.progress.as_progress_view(), state))
+        })
+    }
+}
@@ -0,0 +1,247 @@
+use std::{borrow::Cow, io, thread::ThreadId};
+
+use indexmap::{IndexMap, IndexSet};
+use serde::Serialize;
+
+use super::{
+    Branch, DefaultProgress, InnerProgress, InnerStep, for_each_step_rev, running_durations,
+};
+use crate::Step;
+
+/// Every step that entered or exited the stack in chronological order.
+#[derive(Default)]
+pub(crate) struct Timeline {
+    /// The events are only recorded once enabled with [`DefaultProgress::with_timeline`].
+    enabled: bool,
+    events: Vec<TimelineEvent>,
+    /// The threads that entered a step. Their index is used as their id in the trace.
+    threads: IndexSet<ThreadId>,
+}
+
+struct TimelineEvent {
+    phase: Phase,
+    name: Cow<'static, str>,
+    timestamp: jiff::Timestamp,
+    thread: u64,
+    current: u64,
+    total: u64,
+}
+
+#[derive(Clone, Copy)]
+enum Phase {
+    Enter,
+    Exit,
+}
+
+impl Timeline {
+    /// Record a step entering the stack and returns the id of the thread that entered it.
+    pub fn enter(&mut self, step: &dyn Step, now: jiff::Timestamp) -> u64 {
+        let (thread, _) = self.threads.insert_full(std::thread::current().id());
+        let thread = thread as u64;
+        if self.enabled {
+            self.events
+                .push(TimelineEvent::new(Phase::Enter, step, thread, now));
+        }
+        thread
+    }
+
+    /// Record a step leaving the stack.
+    /// The thread must be the one that entered the step, even if it's popped from another thread.
+    pub fn exit(&mut self, step: &dyn Step, thread: u64, now: jiff::Timestamp) {
+        if self.enabled {
+            self.events
+                .push(TimelineEvent::new(Phase::Exit, step, thread, now));
+        }
+    }
+
+    /// Start recording the events. The steps already running are recorded as if they
+    /// entered the stack at the time they started.
+    fn enable(&mut self, steps: &[InnerStep], branches: &[Branch]) {
+        if self.enabled {
+            return;
+        }
+        self.enabled = true;
+        self.enter_running(steps, branches);
+    }
+
+    fn enter_running(&mut self, steps: &[InnerStep], branches: &[Branch]) {
+        for branch in branches {
+            self.enter_running(&branch.steps, &[]);
+        }
+        for step in steps {
+            self.events.push(TimelineEvent::new(
+                Phase::Enter,
+                &*step.step,
+                step.thread,
+                step.started_at,
+            ));
+            self.enter_running(&[], &step.branches);
+        }
+    }
+}
+
+impl TimelineEvent {
+    fn new(phase: Phase, step: &dyn Step, thread: u64, now: jiff::Timestamp) -> Self {
+        Self {
+            phase,
+            name: step.name(),
+            timestamp: now,
+            thread,
+            current: step.current(),
+            total: step.total(),
+        }
+    }
+
+    fn to_chrome_trace_event(&self, start_time: jiff::Timestamp, pid: u32) -> ChromeTraceEvent {
+        ChromeTraceEvent {
+            name: self.name.clone(),
+            cat: "step",
+            ph: match self.phase {
+                Phase::Enter => "B",
+                Phase::Exit => "E",
+            },
+            ts: self.timestamp.duration_since(start_time).as_secs_f64() * 1_000_000.0,
+            pid,
+            tid: self.thread,
+            args: ChromeTraceArgs {
+                current: self.current,
+                total: self.total,
+            },
+        }
+    }
+}
+
+/// A progress exported in the [Chrome Trace Event format](https://docs.google.com/document/d/1CvAClvFfyA5R-PhYUmn5OOQtYMH4h6I0nSsKchNAySU).
+///
+/// It can be opened in [Perfetto](https://ui.perfetto.dev) or `chrome://tracing`.
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ChromeTrace {
+    pub trace_events: Vec<ChromeTraceEvent>,
+    pub display_time_unit: &'static str,
+}
+
+/// A single duration event of a [`ChromeTrace`].
+#[derive(Debug, Serialize, Clone)]
+pub struct ChromeTraceEvent {
+    pub name: Cow<'static, str>,
+    pub cat: &'static str,
+    /// `"B"` when the step is entered and `"E"` when it exits.
+    pub ph: &'static str,
+    /// The number of microseconds since the progress was created.
+    pub ts: f64,
+    pub pid: u32,
+    pub tid: u64,
+    pub args: ChromeTraceArgs,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ChromeTraceArgs {
+    pub current: u64,
+    pub total: u64,
+}
+
+impl DefaultProgress {
+    /// Record every step entering or exiting the stack to export them with [`DefaultProgress::chrome_trace`].
+    ///
+    /// The timeline grows with every update so it's disabled by default.
+    /// ```
+    /// # use steppe::default::DefaultProgress;
+    /// let progress = DefaultProgress::default().with_timeline();
+    /// ```
+    pub fn with_timeline(self) -> Self {
+        {
+            let mut inner = self.steps.write().unwrap();
+            let InnerProgress {
+                steps,
+                branches,
+                timeline,
+                ..
+            } = &mut *inner;
+            timeline.enable(steps, branches);
+        }
+        self
+    }
+
+    /// Export every step that entered or exited the stack in the Chrome Trace Event format.
+    ///
+    /// The steps that are still running are closed at the time of the call.
+    /// The trace is empty unless the progress was created [`DefaultProgress::with_timeline`].
+    pub fn chrome_trace(&self) -> ChromeTrace {
+        let inner = self.steps.read().unwrap();
+        let InnerProgress {
+            steps,
+            branches,
+            timeline,
+            start_time,
+            ..
+        } = &*inner;
+
+        let now = jiff::Timestamp::now();
+        let pid = std::process::id();
+        let mut running = Vec::new();
+        let mut close = |step: &InnerStep| {
+            running.push(TimelineEvent::new(
+                Phase::Exit,
+                &*step.step,
+                step.thread,
+                now,
+            ))
+        };
+        if timeline.enabled {
+            for_each_step_rev(steps, &mut close);
+            for branch in branches {
+                for_each_step_rev(&branch.steps, &mut close);
+            }
+        }
+
+        ChromeTrace {
+            trace_events: timeline
+                .events
+                .iter()
+                .chain(&running)
+                .map(|event| event.to_chrome_trace_event(*start_time, pid))
+                .collect(),
+            display_time_unit: "ms",
+        }
+    }
+
+    /// Write the [`DefaultProgress::chrome_trace`] as JSON in the writer.
+    pub fn write_chrome_trace(&self, writer: impl io::Write) -> serde_json::Result<()> {
+        serde_json::to_writer(writer, &self.chrome_trace())
+    }
+
+    /// Write the self duration of every step in the folded stacks format used by the
+    /// [flamegraph](https://github.com/brendangregg/FlameGraph) tools such as `inferno`.
+    ///
+    /// There is one line per step with the names of the step and its parents separated by `;`
+    /// followed by the self duration of the step in microseconds:
+    /// ```text
+    /// step1 200000
+    /// step1;step2 1230000
+    /// ```
+    /// The `;` contained in the step names are replaced by `:` to not break the format.
+    pub fn to_folded_stacks(&self, mut writer: impl io::Write) -> io::Result<()> {
+        let stacks = {
+            let inner = self.steps.read().unwrap();
+            let running = running_durations(&inner, jiff::Timestamp::now());
+            let durations = &inner.durations;
+            let mut stacks: IndexMap<String, i128> = IndexMap::new();
+            for occurrence in durations.iter().chain(&running) {
+                let stack = occurrence
+                    .path
+                    .iter()
+                    .map(|name| name.replace(';', ":"))
+                    .collect::<Vec<_>>()
+                    .join(";");
+                *stacks.entry(stack).or_default() += occurrence.self_duration.as_micros();
+            }
+            stacks
+        };
+
+        for (stack, micros) in stacks {
+            writeln!(writer, "{stack} {micros}")?;
+        }
+        Ok(())
+    }
+}
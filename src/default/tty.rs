@@ -0,0 +1,459 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use super::{DefaultProgress, ProgressView, view::format_duration};
+
+/// The width used when the width of the terminal can't be retrieved.
+const DEFAULT_WIDTH: usize = 80;
+/// The maximum width of a progress bar.
+const MAX_BAR_WIDTH: usize = 40;
+/// Under this width the bars are not drawn at all.
+const MIN_BAR_WIDTH: usize = 5;
+
+const CTRL: &str = "\x1b[";
+const UP: &str = "A";
+const CLEAR_LINE: &str = "2K";
+const BLUE: &str = "\x1b[34;1m";
+const GREEN: &str = "\x1b[32;1m";
+const RESET_COLOR: &str = "\x1b[m";
+
+/// A builder to follow the progression of a [`DefaultProgress`] in a human readable way from another thread.
+///
+/// When the output is a tty, one line is drawn for the whole progress and for every level of the stack of steps,
+/// with a bar adapted to the width of the terminal, and redrawn over itself:
+/// ```text
+/// progress      [=========>          ]  45.2% 1m 35s eta 1m 55s
+///   indexing    [====>               ] 1/4  25.0% 1m 35s
+///     documents [=============>      ] 730/1000  73.0% 12s eta 4s
+/// ```
+/// Otherwise, e.g. in the CI, a single plain line is printed at every refresh without any escape code:
+/// ```text
+/// [1m 35s] 45.2% indexing (1/4) > documents (730/1000) eta 1m 55s
+/// ```
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use steppe::default::DefaultProgress;
+/// let progress = DefaultProgress::default();
+/// let follower = progress
+///     .tty_follower()
+///     .refresh(Duration::from_millis(250))
+///     .stderr()
+///     .spawn();
+/// // ... do the work ...
+/// progress.finish();
+/// follower.join().unwrap();
+/// ```
+pub struct TtyFollower {
+    progress: DefaultProgress,
+    refresh: Option<Duration>,
+    writer: Box<dyn Write + Send>,
+    /// The standard stream behind the writer, `None` for an arbitrary writer.
+    stream: Option<Stream>,
+    interactive: Option<bool>,
+    color: Option<bool>,
+    durations: bool,
+    width: Option<usize>,
+}
+
+/// A standard stream we can check is a tty and retrieve the width of.
+#[derive(Clone, Copy)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl Stream {
+    fn is_terminal(self) -> bool {
+        match self {
+            Stream::Stdout => io::stdout().is_terminal(),
+            Stream::Stderr => io::stderr().is_terminal(),
+        }
+    }
+
+    fn width(self) -> Option<usize> {
+        let size = match self {
+            Stream::Stdout => terminal_size::terminal_size_of(io::stdout()),
+            Stream::Stderr => terminal_size::terminal_size_of(io::stderr()),
+        };
+        size.map(|(width, _)| width.0 as usize)
+    }
+}
+
+/// The handle of a running [`TtyFollower`].
+///
+/// Dropping it detaches the follower which stops by itself once the progress is finished.
+pub struct TtyFollowerHandle {
+    progress: DefaultProgress,
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<io::Result<()>>,
+}
+
+impl DefaultProgress {
+    /// Create a [`TtyFollower`] writing in stdout that can be configured before being spawned.
+    pub fn tty_follower(&self) -> TtyFollower {
+        TtyFollower {
+            progress: self.clone(),
+            refresh: None,
+            writer: Box::new(io::stdout()),
+            stream: Some(Stream::Stdout),
+            interactive: None,
+            color: None,
+            durations: true,
+            width: None,
+        }
+    }
+}
+
+impl TtyFollower {
+    /// How often the progress is redrawn. When the output is a tty, the progress is also redrawn as soon
+    /// as a step enters or exits the stack, but never more than once per refresh.
+    ///
+    /// Defaults to 100ms when the output is a tty and 5s otherwise.
+    pub fn refresh(mut self, refresh: Duration) -> Self {
+        self.refresh = Some(refresh);
+        self
+    }
+
+    /// Write the progress in stderr instead of stdout.
+    pub fn stderr(mut self) -> Self {
+        self.writer = Box::new(io::stderr());
+        self.stream = Some(Stream::Stderr);
+        self
+    }
+
+    /// Where to write the progress. Defaults to stdout.
+    ///
+    /// Since we can't know if an arbitrary writer is a tty, it's considered as not being one
+    /// unless [`TtyFollower::interactive`] is specified, and its width defaults to 80 columns.
+    /// Use [`TtyFollower::stderr`] to write in stderr.
+    pub fn writer(mut self, writer: impl Write + Send + 'static) -> Self {
+        self.writer = Box::new(writer);
+        self.stream = None;
+        self
+    }
+
+    /// Whether to draw the bars over themselves or to print plain lines.
+    ///
+    /// Defaults to whether the output is a tty.
+    pub fn interactive(mut self, interactive: bool) -> Self {
+        self.interactive = Some(interactive);
+        self
+    }
+
+    /// Whether to use colors. Defaults to the same value as [`TtyFollower::interactive`].
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = Some(color);
+        self
+    }
+
+    /// Whether to print the accumulated durations of each steps once the progress is finished. Defaults to `true`.
+    pub fn durations(mut self, durations: bool) -> Self {
+        self.durations = durations;
+        self
+    }
+
+    /// The number of columns to fit the bars in.
+    ///
+    /// Defaults to the width of the terminal the output is written to, or 80 if it can't be retrieved.
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// Start following the progress in a new thread.
+    pub fn spawn(self) -> TtyFollowerHandle {
+        let interactive = self
+            .interactive
+            .unwrap_or_else(|| self.stream.is_some_and(Stream::is_terminal));
+        let renderer = Renderer {
+            writer: self.writer,
+            interactive,
+            color: self.color.unwrap_or(interactive),
+            width: self.width,
+            stream: self.stream,
+            lines_of_last_print: 0,
+        };
+        let refresh = self.refresh.unwrap_or(match interactive {
+            true => Duration::from_millis(100),
+            false => Duration::from_secs(5),
+        });
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let progress = self.progress.clone();
+        let thread = {
+            let stop = stop.clone();
+            let durations = self.durations;
+            std::thread::spawn(move || renderer.run(&progress, &stop, refresh, durations))
+        };
+
+        TtyFollowerHandle {
+            progress: self.progress,
+            stop,
+            thread,
+        }
+    }
+}
+
+impl TtyFollowerHandle {
+    /// Wait for the progress to finish and the durations to be printed.
+    pub fn join(self) -> io::Result<()> {
+        self.thread.join().unwrap()
+    }
+
+    /// Stop following the progress right away, without printing the durations, and wait for the thread to exit.
+    pub fn stop(self) -> io::Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+        // Wake up the follower if it's waiting for a change.
+        self.progress.changes.notify();
+        self.join()
+    }
+
+    /// Returns `true` once the follower exited.
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+}
+
+struct Renderer {
+    writer: Box<dyn Write + Send>,
+    interactive: bool,
+    color: bool,
+    width: Option<usize>,
+    stream: Option<Stream>,
+    /// The number of lines to clear before drawing the bars again.
+    lines_of_last_print: usize,
+}
+
+impl Renderer {
+    fn run(
+        mut self,
+        progress: &DefaultProgress,
+        stop: &AtomicBool,
+        refresh: Duration,
+        durations: bool,
+    ) -> io::Result<()> {
+        let stopped = || stop.load(Ordering::Relaxed);
+        let mut last_draw: Option<Instant> = None;
+        loop {
+            if stopped() {
+                self.clear()?;
+                return self.writer.flush();
+            }
+            if progress.is_finished() {
+                break;
+            }
+            // Don't redraw more than once per refresh, even if the steps change faster.
+            if let Some(remaining) = last_draw.and_then(|last| refresh.checked_sub(last.elapsed()))
+            {
+                progress.wait_finished_or(remaining, stopped);
+                continue;
+            }
+            self.draw(&progress.as_progress_view())?;
+            last_draw = Some(Instant::now());
+            match self.interactive {
+                // Redraw as soon as a step enters or exits the stack.
+                true => {
+                    progress.wait_for_change(refresh);
+                }
+                false => progress.wait_finished_or(refresh, stopped),
+            }
+        }
+
+        self.clear()?;
+        if durations {
+            write!(self.writer, "{}", progress.durations_table(self.color))?;
+        }
+        self.writer.flush()
+    }
+
+    fn draw(&mut self, view: &ProgressView) -> io::Result<()> {
+        if !self.interactive {
+            writeln!(self.writer, "{}", plain_line(view))?;
+            return self.writer.flush();
+        }
+
+        let width = self
+            .width
+            .or_else(|| self.stream.and_then(Stream::width))
+            .unwrap_or(DEFAULT_WIDTH);
+        let mut lines = vec![Line::new(
+            0,
+            "progress",
+            Some(view.percentage),
+            None,
+            view.duration,
+            view.eta,
+        )];
+        lines.extend(view.steps.iter().enumerate().map(|(depth, step)| {
+            let counter = match step.total {
+                Some(total) => format!("{}/{total}", step.finished),
+                None => format!("{}/?", step.finished),
+            };
+            let name = &step.current_step;
+            Line::new(
+                depth + 1,
+                name,
+                step.percentage,
+                Some(counter),
+                step.duration,
+                step.eta,
+            )
+        }));
+        // All the bars start and end on the same column.
+        let label_width = lines.iter().map(Line::label_width).max().unwrap_or(0);
+        let stats_width = lines
+            .iter()
+            .map(|line| line.stats.chars().count())
+            .max()
+            .unwrap_or(0);
+        // The space and the two brackets around the bar.
+        let bar_width = width
+            .saturating_sub(label_width + 3 + stats_width)
+            .min(MAX_BAR_WIDTH);
+
+        self.clear()?;
+        for line in &lines {
+            let line = line.render(label_width, bar_width, width, self.color);
+            writeln!(self.writer, "{line}")?;
+        }
+        self.lines_of_last_print = lines.len();
+        self.writer.flush()
+    }
+
+    /// Erase the lines previously drawn.
+    fn clear(&mut self) -> io::Result<()> {
+        for _ in 0..self.lines_of_last_print {
+            write!(self.writer, "{CTRL}{UP}{CTRL}{CLEAR_LINE}")?;
+        }
+        self.lines_of_last_print = 0;
+        Ok(())
+    }
+}
+
+/// A single line describing the whole progress or a step.
+struct Line<'a> {
+    depth: usize,
+    name: &'a str,
+    percentage: Option<f32>,
+    /// Everything displayed after the bar.
+    stats: String,
+}
+
+impl<'a> Line<'a> {
+    fn new(
+        depth: usize,
+        name: &'a str,
+        percentage: Option<f32>,
+        counter: Option<String>,
+        duration: jiff::SignedDuration,
+        eta: Option<jiff::SignedDuration>,
+    ) -> Self {
+        let mut stats = String::new();
+        if let Some(counter) = counter {
+            stats.push_str(&format!(" {counter}"));
+        }
+        match percentage {
+            Some(percentage) => stats.push_str(&format!(" {percentage:5.1}%")),
+            None => stats.push_str("      ?"),
+        }
+        stats.push_str(&format!(" {}", format_duration(duration)));
+        if let Some(eta) = eta {
+            stats.push_str(&format!(" eta {}", format_duration(eta)));
+        }
+        Self {
+            depth,
+            name,
+            percentage,
+            stats,
+        }
+    }
+
+    fn label_width(&self) -> usize {
+        self.depth * 2 + self.name.chars().count()
+    }
+
+    fn render(&self, label_width: usize, bar_width: usize, width: usize, color: bool) -> String {
+        let indent = "  ".repeat(self.depth);
+        let padding = " ".repeat(label_width - self.label_width());
+        let (blue, green, reset_color) = match color {
+            true => (BLUE, GREEN, RESET_COLOR),
+            false => ("", "", ""),
+        };
+        let label = format!("{indent}{blue}{}{reset_color}{padding}", self.name);
+
+        let line = if bar_width < MIN_BAR_WIDTH {
+            format!("{label}{}", self.stats)
+        } else {
+            let bar = bar(self.percentage, bar_width);
+            format!("{label} [{green}{bar}{reset_color}]{}", self.stats)
+        };
+        truncate(&line, width, color)
+    }
+}
+
+/// Draw a bar of `width` characters filled at `percentage`, or full of `?` when the percentage is unknown.
+fn bar(percentage: Option<f32>, width: usize) -> String {
+    let Some(percentage) = percentage else {
+        return "?".repeat(width);
+    };
+    let filled = ((percentage.clamp(0.0, 100.0) / 100.0) * width as f32) as usize;
+    match filled {
+        0 => " ".repeat(width),
+        filled if filled >= width => "=".repeat(width),
+        filled => format!("{}>{}", "=".repeat(filled - 1), " ".repeat(width - filled)),
+    }
+}
+
+/// Cut the line to fit in `width` columns. The escape codes don't take any column.
+fn truncate(line: &str, width: usize, color: bool) -> String {
+    let mut columns = 0;
+    let mut in_escape = false;
+    let mut truncated = String::with_capacity(line.len());
+    for c in line.chars() {
+        if c == '\x1b' {
+            in_escape = true;
+        } else if in_escape {
+            in_escape = c != 'm';
+        } else if columns == width {
+            break;
+        } else {
+            columns += 1;
+        }
+        truncated.push(c);
+    }
+    // Don't let the color leak if we cut the line in the middle of the bar.
+    if color && columns == width {
+        truncated.push_str(RESET_COLOR);
+    }
+    truncated
+}
+
+/// The whole progress on a single line without any escape code.
+fn plain_line(view: &ProgressView) -> String {
+    let steps = view
+        .steps
+        .iter()
+        .map(|step| match step.total {
+            Some(total) => format!("{} ({}/{total})", step.current_step, step.finished),
+            None => format!("{} ({}/?)", step.current_step, step.finished),
+        })
+        .collect::<Vec<_>>()
+        .join(" > ");
+    let mut line = format!(
+        "[{}] {:.1}% {steps}",
+        format_duration(view.duration),
+        view.percentage
+    );
+    if let Some(eta) = view.eta {
+        line.push_str(&format!(" eta {}", format_duration(eta)));
+    }
+    line
+}
@@ -1,9 +1,12 @@
-use std::borrow::Cow;
+use std::{borrow::Cow, fmt};
 
 use indexmap::IndexMap;
 use serde::Serialize;
 
-use super::{DefaultProgress, InnerProgress};
+use super::{
+    Branch, DefaultProgress, DurationTree, InnerProgress, InnerStep, StepDuration, durations,
+    profile::LearnedWeights, rate, running_durations,
+};
 
 /// The returned view of the progress.
 #[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
@@ -11,9 +14,20 @@ use super::{DefaultProgress, InnerProgress};
 #[serde(rename_all = "camelCase")]
 pub struct ProgressView {
     pub steps: Vec<ProgressStepView>,
+    /// The branches forked while no step was running, see [`DefaultProgress::fork`]. Only serialized when there is one.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub branches: Vec<ProgressBranchView>,
     pub percentage: f32,
     #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
     pub duration: jiff::SignedDuration,
+    /// The estimated remaining time, extrapolated linearly from the percentage and the duration.
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::optional")]
+    pub eta: Option<jiff::SignedDuration>,
+    /// The average number of percents completed per second.
+    pub rate: Option<f32>,
+    /// The time at which the progress was cancelled. Only serialized once it's cancelled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cancelled_at: Option<jiff::Timestamp>,
 }
 
 /// The view of the individual steps.
@@ -23,10 +37,61 @@ pub struct ProgressView {
 pub struct ProgressStepView {
     pub current_step: Cow<'static, str>,
     pub finished: u64,
-    pub total: u64,
-    pub percentage: f32,
+    /// `None` when the total of the step is unknown.
+    pub total: Option<u64>,
+    /// `None` when the total of the step is unknown.
+    pub percentage: Option<f32>,
     #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
     pub duration: jiff::SignedDuration,
+    /// The estimated remaining time before the step reaches its total, based on its rate.
+    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::optional")]
+    pub eta: Option<jiff::SignedDuration>,
+    /// The exponentially smoothed number of states completed per second.
+    /// It's only available once the step moved without being replaced, as it happens with an [`crate::AtomicSubStep`].
+    pub rate: Option<f64>,
+    /// The number of units processed per second. Only available for the steps that specify a [`crate::Step::unit`].
+    pub throughput: Option<Throughput>,
+    /// The branches forked while this step was the deepest one and still running, see [`DefaultProgress::fork`].
+    /// Only serialized when there is one.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub branches: Vec<ProgressBranchView>,
+}
+
+/// The view of a branch of the progress.
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgressBranchView {
+    pub name: Cow<'static, str>,
+    pub steps: Vec<ProgressStepView>,
+    /// The percentage of completion of the branch.
+    pub percentage: f32,
+}
+
+/// The throughput of a step counting something.
+#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Throughput {
+    /// The name of the things being counted.
+    pub unit: Cow<'static, str>,
+    /// The number of units per second between the two last views of the progress.
+    pub instantaneous: f64,
+    /// The number of units per second since the step was entered.
+    pub average: f64,
+}
+
+impl fmt::Display for Throughput {
+    /// Display the instantaneous throughput in a human readable way, e.g. `12.3k documents/s`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (value, suffix) = match self.instantaneous {
+            n if n >= 1_000_000_000.0 => (n / 1_000_000_000.0, "G"),
+            n if n >= 1_000_000.0 => (n / 1_000_000.0, "M"),
+            n if n >= 1_000.0 => (n / 1_000.0, "k"),
+            n => (n, ""),
+        };
+        write!(f, "{value:.1}{suffix} {}/s", self.unit)
+    }
 }
 
 impl DefaultProgress {
@@ -47,40 +112,49 @@ impl DefaultProgress {
     ///             "currentStep": "step2",
     ///             "finished": 0,
     ///             "total": 100
+    ///         },
+    ///         {
+    ///             "currentStep": "step3",
+    ///             "finished": 12,
+    ///             "total": null // The total of this step is unknown, it doesn't count in the global percentage
     ///         }
     ///     ],
-    ///     "percentage": 50.0
+    ///     "percentage": 50.0,
+    ///     "duration": "1m 30s", // The time elapsed since the progress was created
+    ///     "eta": "1m 30s", // The estimated remaining time
+    ///     "rate": 0.55 // The number of percents completed per second
     /// }
     /// ```
     pub fn as_progress_view(&self) -> ProgressView {
         let inner = self.steps.read().unwrap();
-        let InnerProgress { steps, .. } = &*inner;
+        let InnerProgress {
+            steps,
+            branches,
+            finished_branches,
+            learned_weights,
+            ..
+        } = &*inner;
 
-        let mut global_percentage = 0.0;
-        let mut prev_factors = 1.0;
         let now = jiff::Timestamp::now();
-
-        let mut step_view = Vec::with_capacity(steps.len());
-        for step in steps.iter() {
-            let name = step.step.name();
-            let total = step.step.total();
-            let current = step.step.current().min(total);
-            prev_factors *= total as f32;
-            global_percentage += (current as f32) / prev_factors;
-
-            step_view.push(ProgressStepView {
-                current_step: name,
-                finished: current,
-                total,
-                percentage: (current as f32) / (total as f32) * 100.0,
-                duration: now.duration_since(step.started_at),
-            });
+        let (step_view, mut done) = stack_view(steps, String::new(), learned_weights, now);
+        let (branches_view, branches_done) = branches_view(branches, "", learned_weights, now);
+        if !branches.is_empty() {
+            // The branches attached to the progress itself share it with the main stack.
+            let participants = branches.len() + finished_branches + !steps.is_empty() as usize;
+            done = (done + branches_done + *finished_branches as f32) / participants as f32;
         }
 
+        let percentage = done * 100.0;
+        let duration = now.duration_since(inner.start_time);
+        let elapsed = duration.as_secs_f32();
         ProgressView {
             steps: step_view,
-            percentage: global_percentage * 100.0,
-            duration: now.duration_since(inner.start_time),
+            branches: branches_view,
+            percentage,
+            duration,
+            eta: rate::linear_eta(duration, percentage),
+            rate: (percentage > 0.0 && elapsed > 0.0).then(|| percentage / elapsed),
+            cancelled_at: inner.cancelled_at,
         }
     }
 
@@ -88,46 +162,44 @@ impl DefaultProgress {
     ///
     /// This is useful to see the bottleneck of the process.
     ///
+    /// When a step is entered multiple times, all of its occurrences are summed together.
+    /// The steps still running are included with their current duration, but they're not
+    /// recorded, which means calling this method multiple times is fine.
+    ///
     /// Returns an ordered map of the step name to the duration:
     /// ```json5
     /// {
-    ///     "step1 > step2": "1.23s", // The duration of the step2 within the step1
-    ///     "step1": "1.43s", // The total duration of the step1. Here we see that most of the time was spent in step1.
+    ///     "step1 > step2": { "totalDuration": "1.23s", "selfDuration": "1.23s", "calls": 2, ... }, // The duration of the step2 within the step1
+    ///     "step1": { "totalDuration": "1.43s", "selfDuration": "0.2s", "calls": 1, ... }, // The total duration of the step1. Here we see that most of the time was spent in step2.
     /// }
+    /// ```
     pub fn accumulated_durations(&self) -> IndexMap<String, StepDuration> {
-        let mut inner = self.steps.write().unwrap();
-        let InnerProgress {
-            steps, durations, ..
-        } = &mut *inner;
-
-        let now = jiff::Timestamp::now();
-        let idx = 0;
-        for (i, step) in steps.iter().skip(idx).enumerate().rev() {
-            let full_name = steps
-                .iter()
-                .take(idx + i + 1)
-                .map(|step| step.step.name())
-                .collect::<Vec<_>>()
-                .join(" > ");
-            durations.push((
-                full_name,
-                now.duration_since(step.started_at),
-                step.time_spent_in_children,
-            ));
-        }
+        let inner = self.steps.read().unwrap();
+        let running = running_durations(&inner, jiff::Timestamp::now());
+        durations::aggregate(inner.durations.iter().chain(&running))
+    }
 
-        durations
-            .iter()
-            .map(|(name, total_duration, self_duration)| {
-                (
-                    name.to_string(),
-                    StepDuration {
-                        total_duration: *total_duration,
-                        self_duration: *self_duration,
-                    },
-                )
-            })
-            .collect()
+    /// Get the accumulated durations of each steps organized as a tree.
+    ///
+    /// It contains the same information as [`DefaultProgress::accumulated_durations`] but
+    /// every step is nested in the step that was running when it was entered:
+    /// ```json5
+    /// [
+    ///     {
+    ///         "name": "step1",
+    ///         "totalDuration": "1.43s",
+    ///         "selfDuration": "0.2s",
+    ///         "calls": 1,
+    ///         "children": [
+    ///             { "name": "step2", "totalDuration": "1.23s", "selfDuration": "1.23s", "calls": 2, "children": [] }
+    ///         ]
+    ///     }
+    /// ]
+    /// ```
+    pub fn duration_tree(&self) -> Vec<DurationTree> {
+        let inner = self.steps.read().unwrap();
+        let running = running_durations(&inner, jiff::Timestamp::now());
+        durations::build_tree(inner.durations.iter().chain(&running))
     }
 
     /// Helper to follow the progression on a tty.
@@ -135,6 +207,8 @@ impl DefaultProgress {
     /// - Refresh the screen every 100ms.
     /// - Display the progress view while the progress is not finished => It will overwrite itself so if you must print other stuff at the same time it might not come out nice :s
     /// - Display the accumulated durations of each steps once the progress is finished and exit the thread.
+    ///
+    /// See [`DefaultProgress::tty_follower`] for a human readable output that can be configured and stopped.
     pub fn follow_progression_on_tty(&self) {
         let this = self.clone();
         std::thread::spawn(move || {
@@ -142,11 +216,8 @@ impl DefaultProgress {
             const CTRL: &str = "\x1b[";
             const UP: &str = "A";
             const CLEAR_LINE: &str = "2K";
-            const BLUE: &str = "\x1b[34;1m";
-            const RESET_COLOR: &str = "\x1b[m";
 
-            while !this.is_finished() {
-                std::thread::sleep(std::time::Duration::from_millis(100));
+            while !this.wait_finished_timeout(std::time::Duration::from_millis(100)) {
                 for _ in 0..lines_of_last_print {
                     print!("{CTRL}{UP}{CTRL}{CLEAR_LINE}");
                 }
@@ -156,36 +227,178 @@ impl DefaultProgress {
                 lines_of_last_print = json.lines().count();
             }
 
-            let durations = this.accumulated_durations();
-            let inner = this.steps.read().unwrap();
-            let duration_since_start = inner
-                .finished_at
-                .unwrap_or_else(|| jiff::Timestamp::now())
-                .duration_since(inner.start_time)
-                .as_secs_f64();
-            for (name, duration) in durations {
-                let StepDuration {
-                    total_duration,
-                    self_duration,
-                } = duration;
-                print!("{BLUE}{name}{RESET_COLOR} => ",);
-                let total_percentage =
-                    (total_duration.as_secs_f64() / duration_since_start) * 100.0;
-                let self_percentage = (self_duration.as_secs_f64() / duration_since_start) * 100.0;
-                let color = get_color_from_percentage(total_percentage);
-                print!("{color}total: {total_duration:?} ({total_percentage:.2}%){RESET_COLOR}",);
-                let color = get_color_from_percentage(self_percentage);
-                println!(" {color}self: {self_duration:?} ({self_percentage:.2}%){RESET_COLOR}",);
+            print!("{}", this.durations_table(true));
+        });
+    }
+
+    /// Format the accumulated durations of each steps with their share of the total duration,
+    /// optionally colored depending on how much time they took, followed by the total duration.
+    pub(crate) fn durations_table(&self, color: bool) -> String {
+        use std::fmt::Write;
+        let (blue, reset_color) = if color {
+            ("\x1b[34;1m", "\x1b[m")
+        } else {
+            ("", "")
+        };
+        let color_from_percentage = |percentage| match color {
+            true => get_color_from_percentage(percentage),
+            false => "",
+        };
+
+        let durations = self.accumulated_durations();
+        let inner = self.steps.read().unwrap();
+        let duration_since_start = inner
+            .finished_at
+            .unwrap_or_else(jiff::Timestamp::now)
+            .duration_since(inner.start_time);
+        let secs_since_start = duration_since_start.as_secs_f64();
+
+        let mut table = String::new();
+        for (name, duration) in durations {
+            let StepDuration {
+                total_duration,
+                self_duration,
+                ..
+            } = duration;
+            let total_percentage = (total_duration.as_secs_f64() / secs_since_start) * 100.0;
+            let self_percentage = (self_duration.as_secs_f64() / secs_since_start) * 100.0;
+            let total_color = color_from_percentage(total_percentage);
+            let self_color = color_from_percentage(self_percentage);
+            writeln!(
+                table,
+                "{blue}{name}{reset_color} => \
+                 {total_color}total: {total_duration:?} ({total_percentage:.2}%){reset_color} \
+                 {self_color}self: {self_duration:?} ({self_percentage:.2}%){reset_color}",
+            )
+            .unwrap();
+        }
+        writeln!(table, "Finished in {duration_since_start:.2?}").unwrap();
+        table
+    }
+}
+
+/// Generate the views of a stack of steps and the fraction of the stack that is done, between `0.0` and `1.0`.
+/// `parent` is the full name of the parent of the first step, to retrieve its learned weight.
+fn stack_view(
+    steps: &[InnerStep],
+    mut parent: String,
+    learned_weights: &LearnedWeights,
+    now: jiff::Timestamp,
+) -> (Vec<ProgressStepView>, f32) {
+    let mut global_percentage = 0.0;
+    let mut prev_factors = 1.0;
+    // Once we encounter a step with an unknown total we can't know how much its children represent.
+    let mut determinate = true;
+
+    let mut step_view = Vec::with_capacity(steps.len());
+    for (i, step) in steps.iter().enumerate() {
+        let name = step.step.name();
+        let total = Some(step.step.total()).filter(|total| *total != 0);
+        let current = match total {
+            Some(total) => step.step.current().min(total),
+            None => step.step.current(),
+        };
+        determinate &= total.is_some();
+        if let (Some(total), true) = (total, determinate) {
+            // The weights learned from a previous run take precedence over the ones specified by the step.
+            let weights = learned_weights
+                .weights(&parent, &name, current, total)
+                .or_else(|| {
+                    let weights = step.step.weights().filter(|weights| {
+                        weights.len() as u64 == total && weights.iter().sum::<f32>() > 0.0
+                    })?;
+                    let done = weights[..current as usize].iter().sum();
+                    // When the step is finished its children can't count anymore.
+                    let weight = weights.get(current as usize).copied().unwrap_or(0.0);
+                    Some((done, weight, weights.iter().sum()))
+                });
+            match weights {
+                Some((done, weight, sum)) => {
+                    global_percentage += done / sum / prev_factors;
+                    prev_factors *= sum / weight;
+                }
+                None => {
+                    prev_factors *= total as f32;
+                    global_percentage += (current as f32) / prev_factors;
+                }
             }
-            println!(
-                "Finished in {:.2?}",
-                inner.finished_at.unwrap().duration_since(inner.start_time)
-            );
+        }
+        if !parent.is_empty() {
+            parent.push_str(" > ");
+        }
+        parent.push_str(&name);
+
+        let (branches, branches_done) =
+            branches_view(&step.branches, &parent, learned_weights, now);
+        if determinate && !step.branches.is_empty() {
+            // The branches and the next step of the stack share the current state of the step.
+            let participants = step.branches.len() + (i + 1 < steps.len()) as usize;
+            global_percentage += branches_done / prev_factors / participants as f32;
+            prev_factors *= participants as f32;
+        }
+
+        let (rate, instantaneous) = step.rate.lock().unwrap().sample(now, current);
+        let duration = now.duration_since(step.started_at);
+        let throughput = step.step.unit().map(|unit| Throughput {
+            unit,
+            instantaneous,
+            average: match duration.as_secs_f64() {
+                0.0 => 0.0,
+                elapsed => current as f64 / elapsed,
+            },
+        });
+
+        step_view.push(ProgressStepView {
+            current_step: name,
+            finished: current,
+            total,
+            percentage: total.map(|total| (current as f32) / (total as f32) * 100.0),
+            duration,
+            eta: total
+                .zip(rate)
+                .and_then(|(total, rate)| rate::rate_eta(total - current, rate)),
+            rate,
+            throughput,
+            branches,
         });
     }
+
+    (step_view, global_percentage)
 }
 
-fn get_color_from_percentage(percentage: f64) -> &'static str {
+/// Generate the views of the branches that are still running and the sum of the fractions
+/// of every branch that is done, the finished ones counting for `1.0`.
+fn branches_view(
+    branches: &[Branch],
+    parent: &str,
+    learned_weights: &LearnedWeights,
+    now: jiff::Timestamp,
+) -> (Vec<ProgressBranchView>, f32) {
+    let mut views = Vec::new();
+    let mut done = 0.0;
+    for branch in branches {
+        if branch.finished {
+            done += 1.0;
+            continue;
+        }
+        let (steps, branch_done) =
+            stack_view(&branch.steps, parent.to_string(), learned_weights, now);
+        done += branch_done;
+        views.push(ProgressBranchView {
+            name: branch.name.clone(),
+            steps,
+            percentage: branch_done * 100.0,
+        });
+    }
+    (views, done)
+}
+
+/// Format a duration truncated to the second in a human readable way, e.g. `1m 35s`.
+pub(crate) fn format_duration(duration: jiff::SignedDuration) -> String {
+    format!("{:#}", jiff::SignedDuration::from_secs(duration.as_secs()))
+}
+
+pub(crate) fn get_color_from_percentage(percentage: f64) -> &'static str {
     const GRAY: &str = "\x1b[30;1m";
     const GREEN: &str = "\x1b[32;1m";
     const YELLOW: &str = "\x1b[33;1m";
@@ -200,13 +413,3 @@ fn get_color_from_percentage(percentage: f64) -> &'static str {
         _ => WHITE,
     }
 }
-
-#[cfg_attr(feature = "utoipa", derive(utoipa::ToSchema))]
-#[derive(Debug, Serialize, Clone)]
-#[serde(rename_all = "camelCase")]
-pub struct StepDuration {
-    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
-    pub total_duration: jiff::SignedDuration,
-    #[serde(serialize_with = "jiff::fmt::serde::duration::friendly::compact::required")]
-    pub self_duration: jiff::SignedDuration,
-}
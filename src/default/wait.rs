@@ -0,0 +1,73 @@
+use std::{
+    sync::{Condvar, Mutex},
+    time::Duration,
+};
+
+use super::DefaultProgress;
+
+/// Wakes up the threads waiting for the progress to change.
+#[derive(Default)]
+pub(crate) struct Changes {
+    /// Increased every time the progress changes.
+    generation: Mutex<u64>,
+    condvar: Condvar,
+}
+
+impl Changes {
+    pub fn notify(&self) {
+        *self.generation.lock().unwrap() += 1;
+        self.condvar.notify_all();
+    }
+}
+
+impl DefaultProgress {
+    /// Block the current thread until the progress is finished.
+    pub fn wait_finished(&self) {
+        let generation = self.changes.generation.lock().unwrap();
+        let _generation = self
+            .changes
+            .condvar
+            .wait_while(generation, |_| !self.is_finished())
+            .unwrap();
+    }
+
+    /// Block the current thread until the progress is finished or the timeout expires.
+    ///
+    /// Returns `true` if the progress is finished.
+    pub fn wait_finished_timeout(&self, timeout: Duration) -> bool {
+        let generation = self.changes.generation.lock().unwrap();
+        let (_generation, _) = self
+            .changes
+            .condvar
+            .wait_timeout_while(generation, timeout, |_| !self.is_finished())
+            .unwrap();
+        self.is_finished()
+    }
+
+    /// Block the current thread until a step enters or exits the stack, the progress finishes, or the timeout expires.
+    ///
+    /// Returns `true` if the progress changed.
+    /// Keep in mind the [`crate::AtomicSubStep`] are updated without notifying anyone.
+    pub fn wait_for_change(&self, timeout: Duration) -> bool {
+        let generation = self.changes.generation.lock().unwrap();
+        let current = *generation;
+        let (_generation, result) = self
+            .changes
+            .condvar
+            .wait_timeout_while(generation, timeout, |generation| *generation == current)
+            .unwrap();
+        !result.timed_out()
+    }
+
+    /// Block the current thread until the progress is finished, `stop` returns `true`, or the timeout expires.
+    ///
+    /// `stop` is only checked when the progress changes.
+    pub(crate) fn wait_finished_or(&self, timeout: Duration, stop: impl Fn() -> bool) {
+        let generation = self.changes.generation.lock().unwrap();
+        let _generation = self
+            .changes
+            .condvar
+            .wait_timeout_while(generation, timeout, |_| !self.is_finished() && !stop())
+            .unwrap();
+    }
+}
@@ -19,19 +19,40 @@ pub trait NamedStep: 'static + Send + Sync + Default {
 /// Structure to quickly define steps that need very quick, lockless updating of their current step.
 /// You can use this struct if:
 /// - The name of the step doesn't change
-/// - The total number of steps doesn't change
+/// - The total number of steps doesn't change, or only needs to be updated lockless as well, see [`AtomicSubStep::new_growable`]
 #[derive(Debug, Clone)]
 pub struct AtomicSubStep<Name: NamedStep> {
     unit_name: Name,
     current: Arc<AtomicU64>,
-    total: u64,
+    total: Arc<AtomicU64>,
 }
 
 impl<Name: NamedStep> AtomicSubStep<Name> {
     pub fn new(total: u64) -> (Arc<AtomicU64>, Self) {
+        let (current, _total, step) = Self::new_growable(total);
+        (current, step)
+    }
+
+    /// Create a step whose total can be updated after its creation.
+    /// It's useful when you discover the things to process while processing them.
+    ///
+    /// Returns the current and the total, they can both be updated without taking any lock.
+    /// ```rust
+    /// use std::sync::atomic::Ordering;
+    /// steppe::make_atomic_progress!(Document alias AtomicDocumentStep => "document");
+    ///
+    /// let (current, total, step) = AtomicDocumentStep::new_growable(0);
+    /// // The producer found new documents
+    /// total.fetch_add(10, Ordering::Relaxed);
+    /// // The consumer processed one of them
+    /// current.fetch_add(1, Ordering::Relaxed);
+    /// ```
+    pub fn new_growable(total: u64) -> (Arc<AtomicU64>, Arc<AtomicU64>, Self) {
         let current = Arc::new(AtomicU64::new(0));
+        let total = Arc::new(AtomicU64::new(total));
         (
             current.clone(),
+            total.clone(),
             Self {
                 current,
                 total,
@@ -51,7 +72,11 @@ impl<Name: NamedStep> Step for AtomicSubStep<Name> {
     }
 
     fn total(&self) -> u64 {
-        self.total
+        self.total.load(Ordering::Relaxed)
+    }
+
+    fn unit(&self) -> Option<Cow<'static, str>> {
+        Some(self.unit_name.name().into())
     }
 }
 
@@ -70,9 +95,36 @@ impl<Name: NamedStep> Step for AtomicSubStep<Name> {
 /// ```
 /// Warning: Even though the syntax looks like a rust enum, it's very case sensitive.
 ///     All the variants unit, named in CamelCase, and finished by a comma.
+///
+/// When the variants don't take the same time you can give them a weight, the variants without a weight weight `1`.
+/// Here, going to the `TheFinalStep` means we're 90% done:
+/// ```rust
+/// steppe::make_enum_progress! {
+///     pub enum WeightedSteps {
+///         TheFirstStep,
+///         #[weight(8)]
+///         TheLongStep,
+///         TheFinalStep,
+///     }
+/// }
+/// ```
 #[macro_export]
 macro_rules! make_enum_progress {
     ($visibility:vis enum $name:ident { $($variant:ident,)+ }) => {
+        $crate::_internal_enum_progress!($visibility enum $name { $($variant,)+ } weights: None);
+    };
+    ($visibility:vis enum $name:ident { $($(#[weight($weight:literal)])? $variant:ident,)+ }) => {
+        $crate::_internal_enum_progress!(
+            $visibility enum $name { $($variant,)+ }
+            weights: Some(std::borrow::Cow::Borrowed(&[$($crate::_internal_weight!($($weight)?)),+]))
+        );
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _internal_enum_progress {
+    ($visibility:vis enum $name:ident { $($variant:ident,)+ } weights: $weights:expr) => {
         #[repr(u8)]
         #[derive(Debug, Clone, Copy, PartialEq, Eq)]
         #[allow(clippy::enum_variant_names)]
@@ -99,10 +151,25 @@ macro_rules! make_enum_progress {
                 use $crate::_internal_count;
                 $crate::_internal_count!($($variant)+) as u64
             }
+
+            fn weights(&self) -> Option<std::borrow::Cow<'static, [f32]>> {
+                $weights
+            }
         }
     };
 }
 
+#[doc(hidden)]
+#[macro_export]
+macro_rules! _internal_weight {
+    () => {
+        1.0
+    };
+    ($weight:literal) => {
+        $weight as f32
+    };
+}
+
 #[doc(hidden)]
 #[macro_export]
 macro_rules! _internal_count {
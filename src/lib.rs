@@ -1,11 +1,19 @@
 #![doc = include_str!("../README.md")]
+// The README is also rendered on GitHub and crates.io, keep its formatting as is.
+#![allow(clippy::doc_lazy_continuation)]
 
 #[cfg(feature = "default-progress")]
 pub mod default;
 mod helper;
+#[cfg(feature = "log")]
+pub mod log;
+mod tee;
+#[cfg(feature = "tracing")]
+pub mod tracing;
 pub use helper::{AtomicSubStep, NamedStep, VariableNameStep};
+pub use tee::TeeProgress;
 
-use std::borrow::Cow;
+use std::{any::TypeId, borrow::Cow, sync::Arc};
 
 #[doc(hidden)]
 pub use convert_case as _private_convert_case;
@@ -16,10 +24,49 @@ pub use convert_case as _private_convert_case;
 /// - The current state of the step.
 ///
 /// The `current` should never exceed the `total`.
+/// A `total` of `0` means the total is unknown (yet). Such a step is displayed like a spinner
+/// and doesn't contribute to the global percentage, neither do its children.
 pub trait Step: 'static + Send + Sync {
     fn name(&self) -> Cow<'static, str>;
     fn current(&self) -> u64;
     fn total(&self) -> u64;
+
+    /// The name of the things counted by the step, if any.
+    /// When specified, the throughput of the step is reported with this unit (e.g. `12k documents/s`).
+    fn unit(&self) -> Option<Cow<'static, str>> {
+        None
+    }
+
+    /// The relative cost of each state of the step, in order.
+    /// It must contain exactly `total` weights, otherwise it's ignored.
+    ///
+    /// By default every state costs the same, which means going from one state to another
+    /// always increases the global percentage by the same amount.
+    fn weights(&self) -> Option<Cow<'static, [f32]>> {
+        None
+    }
+}
+
+impl Step for Box<dyn Step> {
+    fn name(&self) -> Cow<'static, str> {
+        (**self).name()
+    }
+
+    fn current(&self) -> u64 {
+        (**self).current()
+    }
+
+    fn total(&self) -> u64 {
+        (**self).total()
+    }
+
+    fn unit(&self) -> Option<Cow<'static, str>> {
+        (**self).unit()
+    }
+
+    fn weights(&self) -> Option<Cow<'static, [f32]>> {
+        (**self).weights()
+    }
 }
 
 /// The main trait of the crate. It describes the progress of a task.
@@ -27,6 +74,25 @@ pub trait Step: 'static + Send + Sync {
 /// When writing your test you can use the [`NoProgress`] struct to avoid having to import the default-feature..
 pub trait Progress: 'static + Send + Sync {
     fn update(&self, sub_progress: impl Step);
+
+    /// Update the progress with a step whose type has been erased.
+    /// The `type_id` is the [`TypeId`] of the original step, it's used in place of the type of the box
+    /// to know which step is replaced.
+    ///
+    /// The default implementation forwards the box to [`Progress::update`], which is fine for the
+    /// progresses that don't care about the type of the steps.
+    fn update_boxed(&self, sub_progress: Box<dyn Step>, type_id: TypeId) {
+        let _ = type_id;
+        self.update(sub_progress);
+    }
+
+    /// Returns `true` when the user asked to abort the task.
+    ///
+    /// The cancellation is cooperative: it's up to the task to check it regularly,
+    /// typically between two steps, and to stop as soon as possible.
+    fn is_cancelled(&self) -> bool {
+        false
+    }
 }
 
 /// A progress that does nothing.
@@ -37,3 +103,71 @@ pub struct NoProgress;
 impl Progress for NoProgress {
     fn update(&self, _sub_progress: impl Step) {}
 }
+
+/// An object-safe version of [`Progress`], implemented for every progress.
+///
+/// It lets you choose the progress at runtime and store it as a `Box<dyn DynProgress>` or an
+/// `Arc<dyn DynProgress>`, which implement [`Progress`] in turn and can be given to any library.
+/// ```
+/// use std::sync::Arc;
+/// use steppe::{DynProgress, NoProgress, Progress};
+///
+/// fn index(progress: impl Progress) {
+///     // ...
+/// }
+///
+/// let progress: Arc<dyn DynProgress> = Arc::new(NoProgress);
+/// index(progress.clone());
+/// ```
+pub trait DynProgress: 'static + Send + Sync {
+    /// See [`Progress::update_boxed`].
+    fn dyn_update(&self, sub_progress: Box<dyn Step>, type_id: TypeId);
+
+    /// See [`Progress::is_cancelled`].
+    fn dyn_is_cancelled(&self) -> bool;
+}
+
+impl<P: Progress> DynProgress for P {
+    fn dyn_update(&self, sub_progress: Box<dyn Step>, type_id: TypeId) {
+        self.update_boxed(sub_progress, type_id);
+    }
+
+    fn dyn_is_cancelled(&self) -> bool {
+        self.is_cancelled()
+    }
+}
+
+impl dyn DynProgress {
+    /// Update the progress with a step without erasing its type by hand.
+    pub fn update<S: Step>(&self, sub_progress: S) {
+        self.dyn_update(Box::new(sub_progress), TypeId::of::<S>());
+    }
+}
+
+impl Progress for Box<dyn DynProgress> {
+    fn update(&self, sub_progress: impl Step) {
+        (**self).update(sub_progress);
+    }
+
+    fn update_boxed(&self, sub_progress: Box<dyn Step>, type_id: TypeId) {
+        (**self).dyn_update(sub_progress, type_id);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        (**self).dyn_is_cancelled()
+    }
+}
+
+impl Progress for Arc<dyn DynProgress> {
+    fn update(&self, sub_progress: impl Step) {
+        (**self).update(sub_progress);
+    }
+
+    fn update_boxed(&self, sub_progress: Box<dyn Step>, type_id: TypeId) {
+        (**self).dyn_update(sub_progress, type_id);
+    }
+
+    fn is_cancelled(&self) -> bool {
+        (**self).dyn_is_cancelled()
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    any::TypeId,
+    borrow::Cow,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::{Progress, Step};
+
+/// A lightweight progress that logs every change of the stack of steps at the info level with the [`log`] crate.
+///
+/// A line is logged when a step is entered with its full path and its state:
+/// ```text
+/// the first step > we wont go too far this time [0/2]
+/// ```
+/// And another one when it leaves the stack with the time it took:
+/// ```text
+/// the first step > we wont go too far this time finished in 12.34ms
+/// ```
+///
+/// Like the [`crate::default::DefaultProgress`] it can be cloned cheaply and shared everywhere.
+#[derive(Clone, Default)]
+pub struct LogProgress {
+    steps: Arc<Mutex<Vec<LogStep>>>,
+}
+
+struct LogStep {
+    type_id: TypeId,
+    name: Cow<'static, str>,
+    started_at: Instant,
+}
+
+impl Progress for LogProgress {
+    fn update(&self, sub_progress: impl Step) {
+        self.update(sub_progress);
+    }
+
+    fn update_boxed(&self, sub_progress: Box<dyn Step>, type_id: TypeId) {
+        self.update_boxed(sub_progress, type_id);
+    }
+}
+
+impl LogProgress {
+    /// Log the steps leaving the stack if the step was already in it, then log the new step.
+    pub fn update<P: Step>(&self, sub_progress: P) {
+        self.update_boxed(Box::new(sub_progress), TypeId::of::<P>());
+    }
+
+    /// Same as [`LogProgress::update`] with a step whose type has been erased,
+    /// `step_type` must be the [`TypeId`] of the original step.
+    pub fn update_boxed(&self, sub_progress: Box<dyn Step>, step_type: TypeId) {
+        let mut steps = self.steps.lock().unwrap();
+
+        if let Some(idx) = steps.iter().position(|step| step.type_id == step_type) {
+            pop_steps(&mut steps, idx);
+        }
+
+        steps.push(LogStep {
+            type_id: step_type,
+            name: sub_progress.name(),
+            started_at: Instant::now(),
+        });
+        let current = sub_progress.current();
+        match sub_progress.total() {
+            0 => ::log::info!("{} [{current}/?]", path(&steps)),
+            total => ::log::info!("{} [{current}/{total}]", path(&steps)),
+        }
+    }
+
+    /// Log all the steps leaving the stack.
+    pub fn finish(&self) {
+        let mut steps = self.steps.lock().unwrap();
+        pop_steps(&mut steps, 0);
+    }
+}
+
+/// Pop and log the steps starting at `idx`, from the deepest step to the shallowest.
+fn pop_steps(steps: &mut Vec<LogStep>, idx: usize) {
+    while steps.len() > idx {
+        let elapsed = steps.last().unwrap().started_at.elapsed();
+        ::log::info!("{} finished in {elapsed:.2?}", path(steps));
+        steps.pop();
+    }
+}
+
+/// The names of all the steps separated by ` > `.
+fn path(steps: &[LogStep]) -> String {
+    steps
+        .iter()
+        .map(|step| step.name.as_ref())
+        .collect::<Vec<_>>()
+        .join(" > ")
+}
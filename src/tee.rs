@@ -0,0 +1,91 @@
+use std::{any::TypeId, borrow::Cow, sync::Arc};
+
+use crate::{DynProgress, Progress, Step};
+
+/// A progress that forwards every update to several progresses.
+///
+/// The steps don't need to be [`Clone`]: they're shared between all the progresses,
+/// which means an [`crate::AtomicSubStep`] moves everywhere at once.
+/// ```
+/// use steppe::{NoProgress, Progress, TeeProgress};
+///
+/// fn index(progress: impl Progress) {
+///     // ...
+/// }
+///
+/// // e.g. a `DefaultProgress` for the API and a `LogProgress` for the logs of the server
+/// let progress = TeeProgress::new().with(NoProgress).with(NoProgress);
+/// index(progress);
+/// ```
+#[derive(Clone, Default)]
+pub struct TeeProgress {
+    progresses: Vec<Arc<dyn DynProgress>>,
+}
+
+/// A step shared between multiple progresses.
+struct SharedStep(Arc<dyn Step>);
+
+impl Step for SharedStep {
+    fn name(&self) -> Cow<'static, str> {
+        self.0.name()
+    }
+
+    fn current(&self) -> u64 {
+        self.0.current()
+    }
+
+    fn total(&self) -> u64 {
+        self.0.total()
+    }
+
+    fn unit(&self) -> Option<Cow<'static, str>> {
+        self.0.unit()
+    }
+
+    fn weights(&self) -> Option<Cow<'static, [f32]>> {
+        self.0.weights()
+    }
+}
+
+impl TeeProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a progress to forward the updates to.
+    pub fn with(mut self, progress: impl Progress) -> Self {
+        self.push(progress);
+        self
+    }
+
+    /// Add a progress to forward the updates to.
+    pub fn push(&mut self, progress: impl Progress) {
+        self.progresses.push(Arc::new(progress));
+    }
+}
+
+impl Progress for TeeProgress {
+    fn update(&self, sub_progress: impl Step) {
+        let type_id = type_id_of(&sub_progress);
+        self.update_boxed(Box::new(sub_progress), type_id);
+    }
+
+    fn update_boxed(&self, sub_progress: Box<dyn Step>, type_id: TypeId) {
+        let step: Arc<dyn Step> = Arc::from(sub_progress);
+        for progress in &self.progresses {
+            progress.dyn_update(Box::new(SharedStep(step.clone())), type_id);
+        }
+    }
+
+    /// The progress is cancelled as soon as one of the progresses is cancelled.
+    fn is_cancelled(&self) -> bool {
+        self.progresses
+            .iter()
+            .any(|progress| progress.dyn_is_cancelled())
+    }
+}
+
+/// Retrieve the [`TypeId`] of an `impl Trait` argument.
+fn type_id_of<T: 'static>(_: &T) -> TypeId {
+    TypeId::of::<T>()
+}
@@ -0,0 +1,84 @@
+use std::{
+    any::TypeId,
+    sync::{Arc, Mutex},
+};
+
+use ::tracing::{Span, info_span};
+
+use crate::{Progress, Step};
+
+/// A progress that opens a [`tracing`] span for every step.
+///
+/// The span of a step is the child of the span of its parent step and is closed when the step is
+/// replaced or [`TracingProgress::finish`] is called. It records the following fields:
+/// - `name`: The name of the step
+/// - `current`: The state of the step, recorded again when the step is closed
+/// - `total`: The total number of states of the step, recorded again when the step is closed
+///
+/// The spans of the root steps are the children of the span that was current when they were pushed.
+///
+/// Like the [`crate::default::DefaultProgress`] it can be cloned cheaply and shared everywhere.
+#[derive(Clone, Default)]
+pub struct TracingProgress {
+    steps: Arc<Mutex<Vec<TracingStep>>>,
+}
+
+struct TracingStep {
+    type_id: TypeId,
+    step: Box<dyn Step>,
+    span: Span,
+}
+
+impl TracingStep {
+    fn close(self) {
+        self.span.record("current", self.step.current());
+        self.span.record("total", self.step.total());
+        // The span is closed as soon as it's dropped
+    }
+}
+
+impl Progress for TracingProgress {
+    fn update(&self, sub_progress: impl Step) {
+        self.update(sub_progress);
+    }
+
+    fn update_boxed(&self, sub_progress: Box<dyn Step>, type_id: TypeId) {
+        self.update_boxed(sub_progress, type_id);
+    }
+}
+
+impl TracingProgress {
+    /// Close the spans of the step and all its children if it was already in the stack,
+    /// then open a new span for the step.
+    pub fn update<P: Step>(&self, sub_progress: P) {
+        self.update_boxed(Box::new(sub_progress), TypeId::of::<P>());
+    }
+
+    /// Same as [`TracingProgress::update`] with a step whose type has been erased,
+    /// `step_type` must be the [`TypeId`] of the original step.
+    pub fn update_boxed(&self, sub_progress: Box<dyn Step>, step_type: TypeId) {
+        let mut steps = self.steps.lock().unwrap();
+
+        if let Some(idx) = steps.iter().position(|step| step.type_id == step_type) {
+            steps.drain(idx..).rev().for_each(TracingStep::close);
+        }
+
+        let name = sub_progress.name();
+        let (current, total) = (sub_progress.current(), sub_progress.total());
+        let span = match steps.last() {
+            Some(parent) => info_span!(parent: &parent.span, "step", %name, current, total),
+            None => info_span!("step", %name, current, total),
+        };
+        steps.push(TracingStep {
+            type_id: step_type,
+            step: sub_progress,
+            span,
+        });
+    }
+
+    /// Close the spans of all the steps.
+    pub fn finish(&self) {
+        let mut steps = self.steps.lock().unwrap();
+        steps.drain(..).rev().for_each(TracingStep::close);
+    }
+}
@@ -4,7 +4,7 @@ use std::sync::{
     Arc,
     atomic::{AtomicU64, Ordering},
 };
-use steppe::default::{DefaultProgress, StepDuration};
+use steppe::default::{DefaultProgress, DurationTree, StepDuration, StepState};
 use steppe::*;
 
 make_enum_progress! {
@@ -24,13 +24,22 @@ make_enum_progress! {
     }
 }
 
+make_enum_progress! {
+    pub enum WeightedSteps {
+        TheFirstStep,
+        #[weight(8)]
+        TheLongStep,
+        TheFinalStep,
+    }
+}
+
 make_atomic_progress!(CustomUnit alias AtomicCustomUnit => "custom unit");
 
 #[test]
 fn the_test_tm() {
     let progress = DefaultProgress::default();
     progress.update(CustomMainSteps::TheFirstStep);
-    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]" }, @r#"
+    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]", ".**.eta" => "[eta]", ".**.rate" => "[rate]", ".**.instantaneous" => "[throughput]", ".**.average" => "[throughput]" }, @r#"
     {
       "steps": [
         {
@@ -38,15 +47,20 @@ fn the_test_tm() {
           "finished": 0,
           "total": 4,
           "percentage": 0.0,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
         }
       ],
       "percentage": 0.0,
-      "duration": "[duration]"
+      "duration": "[duration]",
+      "eta": "[eta]",
+      "rate": "[rate]"
     }
     "#);
     progress.update(CustomSubSteps::WeWontGoTooFarThisTime);
-    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]" }, @r#"
+    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]", ".**.eta" => "[eta]", ".**.rate" => "[rate]", ".**.instantaneous" => "[throughput]", ".**.average" => "[throughput]" }, @r#"
     {
       "steps": [
         {
@@ -54,22 +68,30 @@ fn the_test_tm() {
           "finished": 0,
           "total": 4,
           "percentage": 0.0,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
         },
         {
           "currentStep": "we wont go too far this time",
           "finished": 0,
           "total": 3,
           "percentage": 0.0,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
         }
       ],
       "percentage": 0.0,
-      "duration": "[duration]"
+      "duration": "[duration]",
+      "eta": "[eta]",
+      "rate": "[rate]"
     }
     "#);
     progress.update(CustomSubSteps::JustOneMore);
-    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]" }, @r#"
+    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]", ".**.eta" => "[eta]", ".**.rate" => "[rate]", ".**.instantaneous" => "[throughput]", ".**.average" => "[throughput]" }, @r#"
     {
       "steps": [
         {
@@ -77,25 +99,33 @@ fn the_test_tm() {
           "finished": 0,
           "total": 4,
           "percentage": 0.0,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
         },
         {
           "currentStep": "just one more",
           "finished": 1,
           "total": 3,
           "percentage": 33.333336,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
         }
       ],
       "percentage": 8.333334,
-      "duration": "[duration]"
+      "duration": "[duration]",
+      "eta": "[eta]",
+      "rate": "[rate]"
     }
     "#);
     progress.update(CustomSubSteps::WeAreDone);
     let (atomic, unit) = AtomicCustomUnit::new(10);
     atomic.fetch_add(6, Ordering::Relaxed);
     progress.update(unit);
-    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]" }, @r#"
+    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]", ".**.eta" => "[eta]", ".**.rate" => "[rate]", ".**.instantaneous" => "[throughput]", ".**.average" => "[throughput]" }, @r#"
     {
       "steps": [
         {
@@ -103,29 +133,44 @@ fn the_test_tm() {
           "finished": 0,
           "total": 4,
           "percentage": 0.0,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
         },
         {
           "currentStep": "we are done",
           "finished": 2,
           "total": 3,
           "percentage": 66.66667,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
         },
         {
           "currentStep": "custom unit",
           "finished": 6,
           "total": 10,
           "percentage": 60.000004,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": {
+            "unit": "custom unit",
+            "instantaneous": "[throughput]",
+            "average": "[throughput]"
+          }
         }
       ],
       "percentage": 21.666666,
-      "duration": "[duration]"
+      "duration": "[duration]",
+      "eta": "[eta]",
+      "rate": "[rate]"
     }
     "#);
     atomic.fetch_add(3, Ordering::Relaxed);
-    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]" }, @r#"
+    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]", ".**.eta" => "[eta]", ".**.rate" => "[rate]", ".**.instantaneous" => "[throughput]", ".**.average" => "[throughput]" }, @r#"
     {
       "steps": [
         {
@@ -133,30 +178,45 @@ fn the_test_tm() {
           "finished": 0,
           "total": 4,
           "percentage": 0.0,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
         },
         {
           "currentStep": "we are done",
           "finished": 2,
           "total": 3,
           "percentage": 66.66667,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
         },
         {
           "currentStep": "custom unit",
           "finished": 9,
           "total": 10,
           "percentage": 90.0,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": {
+            "unit": "custom unit",
+            "instantaneous": "[throughput]",
+            "average": "[throughput]"
+          }
         }
       ],
       "percentage": 24.166668,
-      "duration": "[duration]"
+      "duration": "[duration]",
+      "eta": "[eta]",
+      "rate": "[rate]"
     }
     "#);
     // This should delete both the atomic step and the sub step + We're skipping the second step
     progress.update(CustomMainSteps::TheThirdStep);
-    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]" }, @r#"
+    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]", ".**.eta" => "[eta]", ".**.rate" => "[rate]", ".**.instantaneous" => "[throughput]", ".**.average" => "[throughput]" }, @r#"
     {
       "steps": [
         {
@@ -164,18 +224,23 @@ fn the_test_tm() {
           "finished": 2,
           "total": 4,
           "percentage": 50.0,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
         }
       ],
       "percentage": 50.0,
-      "duration": "[duration]"
+      "duration": "[duration]",
+      "eta": "[eta]",
+      "rate": "[rate]"
     }
     "#);
     let (atomic, unit) = AtomicCustomUnit::new(2);
     // We don't have any check on the max but the percentage should cap itself at the maximum specified value as a "finished" higher than the total means you have a bug
     atomic.fetch_add(1000, Ordering::Relaxed);
     progress.update(unit);
-    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]" }, @r#"
+    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]", ".**.eta" => "[eta]", ".**.rate" => "[rate]", ".**.instantaneous" => "[throughput]", ".**.average" => "[throughput]" }, @r#"
     {
       "steps": [
         {
@@ -183,23 +248,35 @@ fn the_test_tm() {
           "finished": 2,
           "total": 4,
           "percentage": 50.0,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
         },
         {
           "currentStep": "custom unit",
           "finished": 2,
           "total": 2,
           "percentage": 100.0,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": {
+            "unit": "custom unit",
+            "instantaneous": "[throughput]",
+            "average": "[throughput]"
+          }
         }
       ],
       "percentage": 75.0,
-      "duration": "[duration]"
+      "duration": "[duration]",
+      "eta": "[eta]",
+      "rate": "[rate]"
     }
     "#);
     // This should delete the atomic step only
     progress.update(CustomMainSteps::TheFinalStep);
-    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]" }, @r#"
+    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]", ".**.eta" => "[eta]", ".**.rate" => "[rate]", ".**.instantaneous" => "[throughput]", ".**.average" => "[throughput]" }, @r#"
     {
       "steps": [
         {
@@ -207,20 +284,27 @@ fn the_test_tm() {
           "finished": 3,
           "total": 4,
           "percentage": 75.0,
-          "duration": "[duration]"
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
         }
       ],
       "percentage": 75.0,
-      "duration": "[duration]"
+      "duration": "[duration]",
+      "eta": "[eta]",
+      "rate": "[rate]"
     }
     "#);
 
     progress.finish();
-    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]" }, @r#"
+    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]", ".**.eta" => "[eta]", ".**.rate" => "[rate]", ".**.instantaneous" => "[throughput]", ".**.average" => "[throughput]" }, @r#"
     {
       "steps": [],
       "percentage": 0.0,
-      "duration": "[duration]"
+      "duration": "[duration]",
+      "eta": "[eta]",
+      "rate": "[rate]"
     }
     "#);
 
@@ -230,6 +314,10 @@ fn the_test_tm() {
         *v = StepDuration {
             total_duration: SignedDuration::ZERO,
             self_duration: SignedDuration::ZERO,
+            min_duration: SignedDuration::ZERO,
+            max_duration: SignedDuration::ZERO,
+            mean_duration: SignedDuration::ZERO,
+            ..*v
         }
     });
     println!("{:?}", durations);
@@ -237,40 +325,1832 @@ fn the_test_tm() {
     {
       "the first step > we wont go too far this time": {
         "totalDuration": "0s",
-        "selfDuration": "0s"
+        "selfDuration": "0s",
+        "calls": 1,
+        "minDuration": "0s",
+        "maxDuration": "0s",
+        "meanDuration": "0s",
+        "state": {
+          "current": 0,
+          "total": 3
+        }
       },
       "the first step > just one more": {
         "totalDuration": "0s",
-        "selfDuration": "0s"
+        "selfDuration": "0s",
+        "calls": 1,
+        "minDuration": "0s",
+        "maxDuration": "0s",
+        "meanDuration": "0s",
+        "state": {
+          "current": 1,
+          "total": 3
+        }
       },
       "the first step > we are done > custom unit": {
         "totalDuration": "0s",
-        "selfDuration": "0s"
+        "selfDuration": "0s",
+        "calls": 1,
+        "minDuration": "0s",
+        "maxDuration": "0s",
+        "meanDuration": "0s"
       },
       "the first step > we are done": {
         "totalDuration": "0s",
-        "selfDuration": "0s"
+        "selfDuration": "0s",
+        "calls": 1,
+        "minDuration": "0s",
+        "maxDuration": "0s",
+        "meanDuration": "0s",
+        "state": {
+          "current": 2,
+          "total": 3
+        }
       },
       "the first step": {
         "totalDuration": "0s",
-        "selfDuration": "0s"
+        "selfDuration": "0s",
+        "calls": 1,
+        "minDuration": "0s",
+        "maxDuration": "0s",
+        "meanDuration": "0s",
+        "state": {
+          "current": 0,
+          "total": 4
+        }
       },
       "the third step > custom unit": {
         "totalDuration": "0s",
-        "selfDuration": "0s"
+        "selfDuration": "0s",
+        "calls": 1,
+        "minDuration": "0s",
+        "maxDuration": "0s",
+        "meanDuration": "0s",
+        "state": {
+          "current": 1000,
+          "total": 2
+        }
       },
       "the third step": {
         "totalDuration": "0s",
-        "selfDuration": "0s"
+        "selfDuration": "0s",
+        "calls": 1,
+        "minDuration": "0s",
+        "maxDuration": "0s",
+        "meanDuration": "0s",
+        "state": {
+          "current": 2,
+          "total": 4
+        }
       },
       "the final step": {
         "totalDuration": "0s",
-        "selfDuration": "0s"
+        "selfDuration": "0s",
+        "calls": 1,
+        "minDuration": "0s",
+        "maxDuration": "0s",
+        "meanDuration": "0s",
+        "state": {
+          "current": 3,
+          "total": 4
+        }
       }
     }
     "#);
 }
 
+#[test]
+fn repeated_steps_are_aggregated() {
+    let progress = DefaultProgress::default();
+    for _ in 0..3 {
+        progress.update(CustomMainSteps::TheFirstStep);
+        progress.update(CustomSubSteps::WeWontGoTooFarThisTime);
+        progress.update(CustomSubSteps::JustOneMore);
+    }
+    progress.update(CustomMainSteps::TheThirdStep);
+
+    // Calling it multiple times while steps are still running must not record them twice.
+    let first = progress.accumulated_durations();
+    let second = progress.accumulated_durations();
+    assert_eq!(
+        first.iter().map(|(k, v)| (k, v.calls)).collect::<Vec<_>>(),
+        second.iter().map(|(k, v)| (k, v.calls)).collect::<Vec<_>>(),
+    );
+
+    progress.finish();
+    let durations = progress.accumulated_durations();
+    let calls: Vec<_> = durations
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.calls))
+        .collect();
+    assert_eq!(
+        calls,
+        [
+            ("the first step > we wont go too far this time", 3),
+            ("the first step > just one more", 3),
+            ("the first step", 3),
+            ("the third step", 1),
+        ]
+    );
+    for duration in durations.values() {
+        assert!(duration.min_duration <= duration.mean_duration);
+        assert!(duration.mean_duration <= duration.max_duration);
+        assert!(duration.max_duration <= duration.total_duration);
+    }
+}
+
+#[test]
+fn duration_tree() {
+    enum Shard {}
+
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheFirstStep);
+    progress.update(VariableNameStep::<Shard>::new("shard > 1", 0, 2));
+    progress.update(CustomSubSteps::WeWontGoTooFarThisTime);
+    progress.update(VariableNameStep::<Shard>::new("shard > 2", 1, 2));
+    progress.update(CustomSubSteps::WeWontGoTooFarThisTime);
+    progress.update(CustomMainSteps::TheThirdStep);
+    progress.finish();
+
+    assert_json_snapshot!(progress.duration_tree(), { ".**.totalDuration" => "[duration]", ".**.selfDuration" => "[duration]" }, @r#"
+    [
+      {
+        "name": "the first step",
+        "totalDuration": "[duration]",
+        "selfDuration": "[duration]",
+        "calls": 1,
+        "children": [
+          {
+            "name": "shard > 1",
+            "totalDuration": "[duration]",
+            "selfDuration": "[duration]",
+            "calls": 1,
+            "children": [
+              {
+                "name": "we wont go too far this time",
+                "totalDuration": "[duration]",
+                "selfDuration": "[duration]",
+                "calls": 1,
+                "children": []
+              }
+            ]
+          },
+          {
+            "name": "shard > 2",
+            "totalDuration": "[duration]",
+            "selfDuration": "[duration]",
+            "calls": 1,
+            "children": [
+              {
+                "name": "we wont go too far this time",
+                "totalDuration": "[duration]",
+                "selfDuration": "[duration]",
+                "calls": 1,
+                "children": []
+              }
+            ]
+          }
+        ]
+      },
+      {
+        "name": "the third step",
+        "totalDuration": "[duration]",
+        "selfDuration": "[duration]",
+        "calls": 1,
+        "children": []
+      }
+    ]
+    "#);
+}
+
+/// The total duration of every step must be exactly its self duration plus the total duration of its children.
+fn assert_self_durations_are_consistent(nodes: &[DurationTree]) {
+    for node in nodes {
+        let children: SignedDuration = node.children.iter().map(|child| child.total_duration).sum();
+        assert!(
+            !node.self_duration.is_negative(),
+            "{} has a negative self duration: {:?}",
+            node.name,
+            node.self_duration
+        );
+        assert_eq!(
+            node.total_duration,
+            node.self_duration + children,
+            "{} total duration doesn't match its self duration and the duration of its children",
+            node.name
+        );
+        assert_self_durations_are_consistent(&node.children);
+    }
+}
+
+#[test]
+fn self_durations_with_deep_nesting() {
+    enum Level3 {}
+    enum Level4 {}
+
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheFirstStep);
+    progress.update(CustomSubSteps::WeWontGoTooFarThisTime);
+    progress.update(VariableNameStep::<Level3>::new("level 3", 0, 1));
+    progress.update(VariableNameStep::<Level4>::new("level 4", 0, 1));
+    let (atomic, unit) = AtomicCustomUnit::new(10);
+    progress.update(unit);
+    std::thread::sleep(std::time::Duration::from_millis(2));
+    atomic.fetch_add(10, Ordering::Relaxed);
+    // Pop the three deepest steps at once
+    progress.update(CustomSubSteps::JustOneMore);
+    std::thread::sleep(std::time::Duration::from_millis(2));
+
+    // The steps still running must be consistent too
+    assert_self_durations_are_consistent(&progress.duration_tree());
+
+    progress.update(CustomMainSteps::TheFinalStep);
+    progress.finish();
+    let tree = progress.duration_tree();
+    assert_self_durations_are_consistent(&tree);
+
+    // The first step spent nearly all its time in its children
+    let first_step = &tree[0];
+    assert_eq!(first_step.name, "the first step");
+    assert!(first_step.self_duration < first_step.total_duration);
+    let durations = progress.accumulated_durations();
+    let level3 = &durations["the first step > we wont go too far this time > level 3"];
+    let level4 = &durations["the first step > we wont go too far this time > level 3 > level 4"];
+    assert_eq!(
+        level3.total_duration,
+        level3.self_duration + level4.total_duration
+    );
+}
+
+#[test]
+fn self_durations_with_step_reentry() {
+    let progress = DefaultProgress::default();
+    for _ in 0..5 {
+        progress.update(CustomMainSteps::TheFirstStep);
+        for step in [
+            CustomSubSteps::WeWontGoTooFarThisTime,
+            CustomSubSteps::JustOneMore,
+            CustomSubSteps::WeAreDone,
+        ] {
+            progress.update(step);
+            let (atomic, unit) = AtomicCustomUnit::new(1);
+            progress.update(unit);
+            atomic.fetch_add(1, Ordering::Relaxed);
+        }
+        // re-entering the main step pops all its children
+        progress.update(CustomMainSteps::TheThirdStep);
+        assert_self_durations_are_consistent(&progress.duration_tree());
+    }
+    progress.finish();
+
+    let tree = progress.duration_tree();
+    assert_self_durations_are_consistent(&tree);
+    assert_eq!(tree[0].calls, 5);
+    assert_eq!(tree[0].children.len(), 3);
+    assert!(tree[0].children.iter().all(|child| child.calls == 5));
+    assert_eq!(tree[1].calls, 5);
+}
+
+#[test]
+fn chrome_trace() {
+    // Nothing is recorded by default
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheFirstStep);
+    assert!(progress.chrome_trace().trace_events.is_empty());
+    // The steps already running are recorded once it's enabled
+    let progress = progress.with_timeline();
+    progress.update(CustomSubSteps::WeAreDone);
+    let names: Vec<_> = progress
+        .chrome_trace()
+        .trace_events
+        .into_iter()
+        .map(|event| (event.name, event.ph))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            ("the first step".into(), "B"),
+            ("we are done".into(), "B"),
+            ("we are done".into(), "E"),
+            ("the first step".into(), "E"),
+        ]
+    );
+
+    let progress = DefaultProgress::default().with_timeline();
+    progress.update(CustomMainSteps::TheFirstStep);
+    let (atomic, unit) = AtomicCustomUnit::new(10);
+    progress.update(unit);
+    atomic.fetch_add(10, Ordering::Relaxed);
+    let other = progress.clone();
+    std::thread::spawn(move || other.update(CustomMainSteps::TheSecondWeNeverSee))
+        .join()
+        .unwrap();
+    progress.update(CustomSubSteps::WeAreDone);
+
+    // The last step is still running and must be closed by the export
+    assert_json_snapshot!(progress.chrome_trace(), { ".traceEvents[].ts" => "[ts]", ".traceEvents[].pid" => "[pid]" }, @r#"
+    {
+      "traceEvents": [
+        {
+          "name": "the first step",
+          "cat": "step",
+          "ph": "B",
+          "ts": "[ts]",
+          "pid": "[pid]",
+          "tid": 0,
+          "args": {
+            "current": 0,
+            "total": 4
+          }
+        },
+        {
+          "name": "custom unit",
+          "cat": "step",
+          "ph": "B",
+          "ts": "[ts]",
+          "pid": "[pid]",
+          "tid": 0,
+          "args": {
+            "current": 0,
+            "total": 10
+          }
+        },
+        {
+          "name": "custom unit",
+          "cat": "step",
+          "ph": "E",
+          "ts": "[ts]",
+          "pid": "[pid]",
+          "tid": 0,
+          "args": {
+            "current": 10,
+            "total": 10
+          }
+        },
+        {
+          "name": "the first step",
+          "cat": "step",
+          "ph": "E",
+          "ts": "[ts]",
+          "pid": "[pid]",
+          "tid": 0,
+          "args": {
+            "current": 0,
+            "total": 4
+          }
+        },
+        {
+          "name": "the second we never see",
+          "cat": "step",
+          "ph": "B",
+          "ts": "[ts]",
+          "pid": "[pid]",
+          "tid": 1,
+          "args": {
+            "current": 1,
+            "total": 4
+          }
+        },
+        {
+          "name": "we are done",
+          "cat": "step",
+          "ph": "B",
+          "ts": "[ts]",
+          "pid": "[pid]",
+          "tid": 0,
+          "args": {
+            "current": 2,
+            "total": 3
+          }
+        },
+        {
+          "name": "we are done",
+          "cat": "step",
+          "ph": "E",
+          "ts": "[ts]",
+          "pid": "[pid]",
+          "tid": 0,
+          "args": {
+            "current": 2,
+            "total": 3
+          }
+        },
+        {
+          "name": "the second we never see",
+          "cat": "step",
+          "ph": "E",
+          "ts": "[ts]",
+          "pid": "[pid]",
+          "tid": 1,
+          "args": {
+            "current": 1,
+            "total": 4
+          }
+        }
+      ],
+      "displayTimeUnit": "ms"
+    }
+    "#);
+}
+
+#[test]
+fn folded_stacks() {
+    enum Shard {}
+
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheFirstStep);
+    progress.update(VariableNameStep::<Shard>::new("shard;1", 0, 2));
+    progress.update(CustomSubSteps::JustOneMore);
+    progress.update(VariableNameStep::<Shard>::new("shard;1", 0, 2));
+    progress.update(CustomMainSteps::TheThirdStep);
+    progress.finish();
+
+    let mut output = Vec::new();
+    progress.to_folded_stacks(&mut output).unwrap();
+    let output = String::from_utf8(output).unwrap();
+    let stacks: Vec<_> = output
+        .lines()
+        .map(|line| {
+            let (stack, micros) = line.rsplit_once(' ').unwrap();
+            assert!(micros.parse::<u64>().is_ok(), "{line}");
+            stack
+        })
+        .collect();
+    assert_eq!(
+        stacks,
+        [
+            "the first step;shard:1;just one more",
+            "the first step;shard:1",
+            "the first step",
+            "the third step",
+        ]
+    );
+}
+
+#[test]
+fn eta_and_rate() {
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheFirstStep);
+    let (atomic, unit) = AtomicCustomUnit::new(100);
+    progress.update(unit);
+
+    // Nothing moved yet, we can't estimate anything
+    let view = progress.as_progress_view();
+    assert_eq!(view.eta, None);
+    assert_eq!(view.rate, None);
+    assert!(
+        view.steps
+            .iter()
+            .all(|step| step.rate.is_none() && step.eta.is_none())
+    );
+
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    atomic.fetch_add(10, Ordering::Relaxed);
+    let view = progress.as_progress_view();
+    assert!(view.eta.is_some());
+    assert!(view.rate.unwrap() > 0.0);
+    // The enum step never moves by itself
+    assert_eq!(view.steps[0].rate, None);
+    let step = &view.steps[1];
+    assert!(step.rate.unwrap() > 0.0);
+    assert!(step.eta.unwrap().is_positive());
+
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    atomic.fetch_add(90, Ordering::Relaxed);
+    let view = progress.as_progress_view();
+    assert_eq!(view.steps[1].eta, Some(SignedDuration::ZERO));
+}
+
+#[test]
+fn throughput() {
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheFirstStep);
+    let (atomic, unit) = AtomicCustomUnit::new(100_000);
+    progress.update(unit);
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    atomic.fetch_add(50_000, Ordering::Relaxed);
+
+    let view = progress.as_progress_view();
+    assert!(view.steps[0].throughput.is_none());
+    let throughput = view.steps[1].throughput.as_ref().unwrap();
+    assert_eq!(throughput.unit, "custom unit");
+    assert!(throughput.instantaneous > 0.0);
+    assert!(throughput.average > 0.0);
+    // Generating a view doesn't reset the rates seen by the next one
+    let next = progress.as_progress_view();
+    let next_throughput = next.steps[1].throughput.as_ref().unwrap();
+    assert!((next_throughput.instantaneous / throughput.instantaneous - 1.0).abs() < 0.5);
+    assert!((next.steps[1].rate.unwrap() / view.steps[1].rate.unwrap() - 1.0).abs() < 0.5);
+
+    let throughput = steppe::default::Throughput {
+        unit: "documents".into(),
+        instantaneous: 12_345.0,
+        average: 10_000.0,
+    };
+    assert_eq!(throughput.to_string(), "12.3k documents/s");
+}
+
+#[test]
+fn indeterminate_steps() {
+    enum Discovery {}
+
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheSecondWeNeverSee);
+    progress.update(VariableNameStep::<Discovery>::new(
+        "discovering files",
+        12,
+        0,
+    ));
+    // The children of an indeterminate step can't count in the global percentage either
+    let (atomic, unit) = AtomicCustomUnit::new(10);
+    atomic.fetch_add(5, Ordering::Relaxed);
+    progress.update(unit);
+
+    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]", ".**.eta" => "[eta]", ".**.rate" => "[rate]", ".**.instantaneous" => "[throughput]", ".**.average" => "[throughput]" }, @r#"
+    {
+      "steps": [
+        {
+          "currentStep": "the second we never see",
+          "finished": 1,
+          "total": 4,
+          "percentage": 25.0,
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
+        },
+        {
+          "currentStep": "discovering files",
+          "finished": 12,
+          "total": null,
+          "percentage": null,
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null
+        },
+        {
+          "currentStep": "custom unit",
+          "finished": 5,
+          "total": 10,
+          "percentage": 50.0,
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": {
+            "unit": "custom unit",
+            "instantaneous": "[throughput]",
+            "average": "[throughput]"
+          }
+        }
+      ],
+      "percentage": 25.0,
+      "duration": "[duration]",
+      "eta": "[eta]",
+      "rate": "[rate]"
+    }
+    "#);
+}
+
+#[test]
+fn growable_total() {
+    let progress = DefaultProgress::default();
+    let (current, total, step) = AtomicCustomUnit::new_growable(0);
+    progress.update(step);
+
+    let view = progress.as_progress_view();
+    assert_eq!(view.steps[0].total, None);
+
+    total.fetch_add(10, Ordering::Relaxed);
+    current.fetch_add(5, Ordering::Relaxed);
+    let view = progress.as_progress_view();
+    assert_eq!((view.steps[0].finished, view.steps[0].total), (5, Some(10)));
+    assert_eq!(view.percentage, 50.0);
+
+    total.fetch_add(10, Ordering::Relaxed);
+    let view = progress.as_progress_view();
+    assert_eq!((view.steps[0].finished, view.steps[0].total), (5, Some(20)));
+    assert_eq!(view.percentage, 25.0);
+}
+
+#[test]
+fn weighted_steps() {
+    assert_eq!(CustomMainSteps::TheFirstStep.weights(), None);
+    assert_eq!(
+        WeightedSteps::TheFirstStep.weights().as_deref(),
+        Some(&[1.0, 8.0, 1.0][..])
+    );
+
+    let progress = DefaultProgress::default();
+    progress.update(WeightedSteps::TheFirstStep);
+    assert_eq!(progress.as_progress_view().percentage, 0.0);
+    progress.update(WeightedSteps::TheLongStep);
+    assert_eq!(progress.as_progress_view().percentage, 10.0);
+    // The children of a weighted step only count for the weight of the current state
+    let (atomic, unit) = AtomicCustomUnit::new(2);
+    atomic.fetch_add(1, Ordering::Relaxed);
+    progress.update(unit);
+    assert_eq!(progress.as_progress_view().percentage, 50.0);
+    progress.update(WeightedSteps::TheFinalStep);
+    assert_eq!(progress.as_progress_view().percentage, 90.0);
+}
+
+#[test]
+fn learned_weights() {
+    let duration = |total: &str, state: Option<(u64, u64)>| {
+        let state = state
+            .map(|(current, total)| {
+                format!(r#", "state": {{ "current": {current}, "total": {total} }}"#)
+            })
+            .unwrap_or_default();
+        format!(
+            r#"{{ "totalDuration": "{total}", "selfDuration": "{total}", "calls": 1, "minDuration": "{total}", "maxDuration": "{total}", "meanDuration": "{total}"{state} }}"#
+        )
+    };
+    let profile = format!(
+        r#"{{
+            "the first step > we wont go too far this time": {},
+            "the first step > just one more": {},
+            "the first step > we are done": {},
+            "the first step": {},
+            "the third step": {}
+        }}"#,
+        duration("3s", Some((0, 3))),
+        duration("1s", Some((1, 3))),
+        duration("2s", Some((2, 3))),
+        duration("6s", Some((0, 4))),
+        duration("34s", Some((2, 4))),
+    );
+    let progress = DefaultProgress::with_json_profile(profile.as_bytes()).unwrap();
+    let mut previous = 0.0;
+    let mut percentage = |step: &dyn Fn(&DefaultProgress)| {
+        step(&progress);
+        let percentage = progress.as_progress_view().percentage;
+        assert!(percentage >= previous, "{percentage} < {previous}");
+        previous = percentage;
+        percentage
+    };
+
+    assert_eq!(
+        percentage(&|p| p.update(CustomMainSteps::TheFirstStep)),
+        0.0
+    );
+    // The sub steps were all seen, they take their share of the first step
+    let p = percentage(&|p| p.update(CustomSubSteps::JustOneMore));
+    assert!((p - 7.5).abs() < 1e-4);
+    let p = percentage(&|p| p.update(CustomSubSteps::WeAreDone));
+    assert!((p - 10.0).abs() < 1e-4);
+    // The second step was never seen, the third one starts right after the first one
+    let p = percentage(&|p| p.update(CustomMainSteps::TheThirdStep));
+    assert!((p - 15.0).abs() < 1e-4);
+    // The final step was never seen, it had no weight last time
+    assert_eq!(
+        percentage(&|p| p.update(CustomMainSteps::TheFinalStep)),
+        100.0
+    );
+    progress.finish();
+
+    // A step that isn't an enum still moves inside its share
+    struct Indexing;
+    let profile = format!(
+        r#"{{ "documents": {}, "words": {} }}"#,
+        duration("30s", None),
+        duration("10s", None),
+    );
+    let progress = DefaultProgress::with_json_profile(profile.as_bytes()).unwrap();
+    let percentage = |name: &str, current: u64| {
+        progress.update(VariableNameStep::<Indexing>::new(name, current, 100));
+        progress.as_progress_view().percentage
+    };
+    assert_eq!(percentage("documents", 0), 0.0);
+    assert!((percentage("documents", 50) - 37.5).abs() < 1e-4);
+    assert!((percentage("documents", 99) - 74.25).abs() < 1e-4);
+    assert!((percentage("words", 50) - 87.5).abs() < 1e-4);
+    progress.finish();
+
+    // The profile of a run can be reused for the next one
+    let profile = serde_json::to_string(&progress.accumulated_durations()).unwrap();
+    DefaultProgress::with_json_profile(profile.as_bytes()).unwrap();
+
+    // Only the steps that didn't move record their state
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheThirdStep);
+    let (atomic, unit) = AtomicCustomUnit::new(10);
+    progress.update(unit);
+    atomic.fetch_add(3, Ordering::Relaxed);
+    progress.finish();
+    let states: Vec<_> = progress
+        .accumulated_durations()
+        .into_iter()
+        .map(|(name, duration)| (name, duration.state))
+        .collect();
+    assert_eq!(
+        states,
+        [
+            ("the third step > custom unit".to_string(), None),
+            (
+                "the third step".to_string(),
+                Some(StepState {
+                    current: 2,
+                    total: 4
+                })
+            ),
+        ]
+    );
+}
+
+#[test]
+fn save_and_load_snapshot() {
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheFirstStep);
+    progress.update(CustomSubSteps::WeWontGoTooFarThisTime);
+    progress.update(CustomSubSteps::JustOneMore);
+    let (atomic, unit) = AtomicCustomUnit::new(10);
+    atomic.fetch_add(3, Ordering::Relaxed);
+    progress.update(unit);
+
+    let mut saved = Vec::new();
+    progress.save_snapshot(&mut saved).unwrap();
+    let restored = DefaultProgress::load_snapshot(saved.as_slice()).unwrap();
+
+    let steps = |progress: &DefaultProgress| {
+        progress
+            .as_progress_view()
+            .steps
+            .into_iter()
+            .map(|step| (step.current_step, step.finished, step.total))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(steps(&progress), steps(&restored));
+    assert_eq!(
+        progress.as_progress_view().percentage,
+        restored.as_progress_view().percentage
+    );
+    assert!(!restored.is_finished());
+
+    // The restored steps are recorded when the progress finishes
+    restored.finish();
+    let durations: Vec<_> = restored
+        .accumulated_durations()
+        .into_iter()
+        .map(|(name, duration)| (name, duration.calls))
+        .collect();
+    assert_eq!(
+        durations,
+        [
+            (
+                "the first step > we wont go too far this time".to_string(),
+                1
+            ),
+            (
+                "the first step > just one more > custom unit".to_string(),
+                1
+            ),
+            ("the first step > just one more".to_string(), 1),
+            ("the first step".to_string(), 1),
+        ]
+    );
+
+    // The branches are part of the snapshot
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheFirstStep);
+    let first = progress.fork("first shard");
+    let second = progress.fork("second shard");
+    first.update(CustomSubSteps::JustOneMore);
+    second.update(CustomSubSteps::WeAreDone);
+    drop(first);
+    let root = DefaultProgress::default();
+    let root_branch = root.fork("root");
+    root_branch.update(CustomSubSteps::JustOneMore);
+
+    let branches = |branches: Vec<steppe::default::ProgressBranchView>| {
+        branches
+            .into_iter()
+            .map(|branch| {
+                let steps: Vec<_> = branch
+                    .steps
+                    .into_iter()
+                    .map(|step| (step.current_step, step.finished))
+                    .collect();
+                (branch.name, steps, branch.percentage)
+            })
+            .collect::<Vec<_>>()
+    };
+    for progress in [&progress, &root] {
+        let restored = DefaultProgress::from_snapshot(progress.snapshot());
+        let (mut view, mut restored_view) =
+            (progress.as_progress_view(), restored.as_progress_view());
+        assert_eq!(branches(view.branches), branches(restored_view.branches));
+        assert_eq!(view.percentage, restored_view.percentage);
+        if let (Some(step), Some(restored_step)) = (view.steps.pop(), restored_view.steps.pop()) {
+            assert_eq!(branches(step.branches), branches(restored_step.branches));
+        }
+    }
+
+    let restored = DefaultProgress::from_snapshot(progress.snapshot());
+    restored.finish();
+    assert_eq!(
+        restored.accumulated_durations().keys().collect::<Vec<_>>(),
+        [
+            "the first step > just one more",
+            "the first step > we are done",
+            "the first step"
+        ]
+    );
+}
+
+#[test]
+fn profile_diff() {
+    use steppe::default::ProfileDiff;
+
+    let duration = |total: &str, self_duration: &str| {
+        format!(
+            r#"{{ "totalDuration": "{total}", "selfDuration": "{self_duration}", "calls": 1, "minDuration": "{total}", "maxDuration": "{total}", "meanDuration": "{total}" }}"#
+        )
+    };
+    let before = format!(
+        r#"{{ "a > b": {}, "a > c": {}, "a": {} }}"#,
+        duration("2s", "2s"),
+        duration("1s", "1s"),
+        duration("4s", "1s"),
+    );
+    let after = format!(
+        r#"{{ "a > b": {}, "a > d": {}, "a": {} }}"#,
+        duration("3s", "3s"),
+        duration("500ms", "500ms"),
+        duration("4s", "500ms"),
+    );
+    let before = serde_json::from_str(&before).unwrap();
+    let after = serde_json::from_str(&after).unwrap();
+    let diff = ProfileDiff::new(&before, &after);
+
+    let mut output = Vec::new();
+    diff.write_colored(&mut output).unwrap();
+    // Make the escape codes visible
+    let output = String::from_utf8(output).unwrap().replace('\x1b', "^[");
+    insta::assert_snapshot!(output, @r#"
+    ^[[34;1ma > b^[[m => ^[[31;1mtotal: 2s -> 3s (+1s, +50.00%)^[[m ^[[31;1mself: 2s -> 3s (+1s, +50.00%)^[[m
+    ^[[34;1ma > d^[[m => ^[[37;1madded, total: 500ms^[[m
+    ^[[34;1ma^[[m => ^[[30;1mtotal: 4s -> 4s (+0s, +0.00%)^[[m ^[[30;1mself: 1s -> 500ms (-500ms, -50.00%)^[[m
+    ^[[34;1ma > c^[[m => ^[[30;1mremoved, total: 1s^[[m
+    "#);
+    assert_json_snapshot!(diff, @r#"
+    {
+      "steps": {
+        "a > b": {
+          "status": "changed",
+          "totalDuration": {
+            "before": "2s",
+            "after": "3s",
+            "absolute": "+1s",
+            "relative": 50.0
+          },
+          "selfDuration": {
+            "before": "2s",
+            "after": "3s",
+            "absolute": "+1s",
+            "relative": 50.0
+          }
+        },
+        "a > d": {
+          "status": "added",
+          "after": {
+            "totalDuration": "500ms",
+            "selfDuration": "500ms",
+            "calls": 1,
+            "minDuration": "500ms",
+            "maxDuration": "500ms",
+            "meanDuration": "500ms"
+          }
+        },
+        "a": {
+          "status": "changed",
+          "totalDuration": {
+            "before": "4s",
+            "after": "4s",
+            "absolute": "+0s",
+            "relative": 0.0
+          },
+          "selfDuration": {
+            "before": "1s",
+            "after": "500ms",
+            "absolute": "-500ms",
+            "relative": -50.0
+          }
+        },
+        "a > c": {
+          "status": "removed",
+          "before": {
+            "totalDuration": "1s",
+            "selfDuration": "1s",
+            "calls": 1,
+            "minDuration": "1s",
+            "maxDuration": "1s",
+            "meanDuration": "1s"
+          }
+        }
+      }
+    }
+    "#);
+}
+
+#[test]
+fn listeners() {
+    use std::sync::Mutex;
+    use steppe::default::ProgressEvent;
+
+    let progress = DefaultProgress::default();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let id = progress.subscribe({
+        let events = events.clone();
+        let progress = progress.clone();
+        move |event| {
+            // The listener can use the progress without deadlocking
+            let _ = progress.as_progress_view();
+            let event = match event {
+                ProgressEvent::StepEntered {
+                    path,
+                    current,
+                    total,
+                } => format!("entered {} ({current}/{total})", path.join(" > ")),
+                ProgressEvent::StepExited { path, .. } => format!("exited {}", path.join(" > ")),
+                ProgressEvent::Cancelled { .. } => "cancelled".to_string(),
+                ProgressEvent::Finished { .. } => "finished".to_string(),
+            };
+            events.lock().unwrap().push(event);
+        }
+    });
+
+    progress.update(CustomMainSteps::TheFirstStep);
+    progress.update(CustomSubSteps::JustOneMore);
+    progress.update(CustomMainSteps::TheThirdStep);
+    progress.finish();
+    progress.unsubscribe(id);
+    progress.update(CustomMainSteps::TheFinalStep);
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        [
+            "entered the first step (0/4)",
+            "entered the first step > just one more (1/3)",
+            "exited the first step > just one more",
+            "exited the first step",
+            "entered the third step (2/4)",
+            "exited the third step",
+            "finished",
+        ]
+    );
+}
+
+#[test]
+fn cancellation() {
+    /// A library function that stops at the step boundaries once cancelled.
+    fn index(progress: &impl Progress) -> Vec<CustomMainSteps> {
+        let mut done = Vec::new();
+        for step in [
+            CustomMainSteps::TheFirstStep,
+            CustomMainSteps::TheSecondWeNeverSee,
+            CustomMainSteps::TheThirdStep,
+        ] {
+            if progress.is_cancelled() {
+                break;
+            }
+            progress.update(step);
+            done.push(step);
+        }
+        done
+    }
+
+    assert!(!NoProgress.is_cancelled());
+    assert_eq!(index(&NoProgress).len(), 3);
+
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheFirstStep);
+    progress.update(CustomSubSteps::WeWontGoTooFarThisTime);
+    assert!(progress.as_progress_view().cancelled_at.is_none());
+    progress.cancel();
+    assert!(progress.is_cancelled());
+    assert_eq!(index(&progress), []);
+    let cancelled_at = progress.as_progress_view().cancelled_at.unwrap();
+    // Cancelling twice doesn't change anything
+    progress.cancel();
+    assert_eq!(progress.as_progress_view().cancelled_at, Some(cancelled_at));
+
+    // The steps entered after the cancellation, to clean up, aren't flagged
+    progress.update(CustomMainSteps::TheFinalStep);
+    progress.finish();
+    assert!(progress.is_finished() && progress.is_cancelled());
+
+    let durations = progress.accumulated_durations();
+    let cancelled: Vec<_> = durations
+        .iter()
+        .map(|(name, duration)| (name.as_str(), duration.cancelled))
+        .collect();
+    assert_eq!(
+        cancelled,
+        [
+            ("the first step > we wont go too far this time", true),
+            ("the first step", true),
+            ("the final step", false),
+        ]
+    );
+    let json = serde_json::to_value(&durations).unwrap();
+    assert_eq!(json["the first step"]["cancelled"], true);
+    assert!(json["the final step"].get("cancelled").is_none());
+    let tree = progress.duration_tree();
+    assert!(tree[0].cancelled && !tree[1].cancelled);
+
+    // The cancellation survives a snapshot
+    let restored = DefaultProgress::from_snapshot(progress.snapshot());
+    assert_eq!(restored.as_progress_view().cancelled_at, Some(cancelled_at));
+    assert!(restored.accumulated_durations()["the first step"].cancelled);
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn view_stream() {
+    use futures_util::StreamExt;
+    use std::time::Duration;
+
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheFirstStep);
+    let stream = progress.view_stream(Duration::from_secs(3600));
+    let updater = std::thread::spawn({
+        let progress = progress.clone();
+        move || {
+            std::thread::sleep(Duration::from_millis(50));
+            progress.update(CustomMainSteps::TheThirdStep);
+            std::thread::sleep(Duration::from_millis(50));
+            progress.finish();
+        }
+    });
+
+    // We would wait for an hour if the stream wasn't woken up by the changes
+    let views: Vec<_> = tokio::time::timeout(Duration::from_secs(10), stream.collect())
+        .await
+        .unwrap();
+    updater.join().unwrap();
+    let steps: Vec<_> = views
+        .iter()
+        .map(|view| {
+            view.steps
+                .iter()
+                .map(|step| step.current_step.clone())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    assert_eq!(
+        steps,
+        [vec!["the first step"], vec!["the third step"], vec![]]
+    );
+}
+
+#[test]
+fn wait_for_the_progress() {
+    use std::time::Duration;
+
+    let progress = DefaultProgress::default();
+    assert!(!progress.wait_finished_timeout(Duration::from_millis(1)));
+    assert!(!progress.wait_for_change(Duration::from_millis(1)));
+
+    let updater = std::thread::spawn({
+        let progress = progress.clone();
+        move || {
+            std::thread::sleep(Duration::from_millis(20));
+            progress.update(CustomMainSteps::TheFirstStep);
+            std::thread::sleep(Duration::from_millis(20));
+            progress.finish();
+        }
+    });
+    assert!(progress.wait_for_change(Duration::from_secs(10)));
+    progress.wait_finished();
+    assert!(progress.is_finished());
+    assert!(progress.wait_finished_timeout(Duration::from_millis(1)));
+    updater.join().unwrap();
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn tracing_spans() {
+    use ::tracing::{
+        Event, Metadata, Subscriber,
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+    };
+    use std::sync::Mutex;
+    use steppe::tracing::TracingProgress;
+
+    /// Records the name of the spans when they're opened and closed.
+    #[derive(Default)]
+    struct Recorder {
+        names: Mutex<Vec<String>>,
+        log: Mutex<Vec<String>>,
+    }
+
+    struct NameVisitor(String);
+    impl Visit for NameVisitor {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "name" {
+                self.0 = format!("{value:?}");
+            }
+        }
+    }
+
+    impl Subscriber for &'static Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut visitor = NameVisitor(String::new());
+            span.record(&mut visitor);
+            let mut names = self.names.lock().unwrap();
+            let parent = span
+                .parent()
+                .map(|id| names[id.into_u64() as usize - 1].clone());
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("open {} in {parent:?}", visitor.0));
+            names.push(visitor.0);
+            Id::from_u64(names.len() as u64)
+        }
+        fn try_close(&self, id: Id) -> bool {
+            let name = self.names.lock().unwrap()[id.into_u64() as usize - 1].clone();
+            self.log.lock().unwrap().push(format!("close {name}"));
+            true
+        }
+        fn record(&self, _: &Id, _: &Record<'_>) {}
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    let recorder: &'static Recorder = Box::leak(Box::default());
+    ::tracing::subscriber::with_default(recorder, || {
+        let progress = TracingProgress::default();
+        progress.update(CustomMainSteps::TheFirstStep);
+        progress.update(CustomSubSteps::WeWontGoTooFarThisTime);
+        progress.update(CustomMainSteps::TheThirdStep);
+        progress.update(CustomSubSteps::JustOneMore);
+        progress.finish();
+    });
+
+    assert_eq!(
+        *recorder.log.lock().unwrap(),
+        [
+            "open the first step in None",
+            "open we wont go too far this time in Some(\"the first step\")",
+            "close we wont go too far this time",
+            "close the first step",
+            "open the third step in None",
+            "open just one more in Some(\"the third step\")",
+            "close just one more",
+            "close the third step",
+        ]
+    );
+}
+
+#[cfg(feature = "log")]
+#[test]
+fn log_step_transitions() {
+    use std::sync::Mutex;
+    use steppe::log::LogProgress;
+
+    /// Keeps the logs of this crate, without the elapsed time which is not deterministic.
+    struct Recorder(Mutex<Vec<String>>);
+
+    impl ::log::Log for Recorder {
+        fn enabled(&self, metadata: &::log::Metadata) -> bool {
+            metadata.target().starts_with("steppe")
+        }
+        fn log(&self, record: &::log::Record) {
+            if self.enabled(record.metadata()) {
+                let line = record.args().to_string();
+                let line = match line.split_once(" finished in ") {
+                    Some((path, _)) => format!("{path} finished"),
+                    None => line,
+                };
+                self.0
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {line}", record.level()));
+            }
+        }
+        fn flush(&self) {}
+    }
+
+    static RECORDER: Recorder = Recorder(Mutex::new(Vec::new()));
+    ::log::set_logger(&RECORDER).unwrap();
+    ::log::set_max_level(::log::LevelFilter::Info);
+
+    let progress = LogProgress::default();
+    progress.update(CustomMainSteps::TheFirstStep);
+    progress.update(CustomSubSteps::WeWontGoTooFarThisTime);
+    progress.update(CustomSubSteps::JustOneMore);
+    progress.update(CustomMainSteps::TheThirdStep);
+    progress.finish();
+
+    insta::assert_snapshot!(RECORDER.0.lock().unwrap().join("\n"), @r#"
+    INFO the first step [0/4]
+    INFO the first step > we wont go too far this time [0/3]
+    INFO the first step > we wont go too far this time finished
+    INFO the first step > just one more [1/3]
+    INFO the first step > just one more finished
+    INFO the first step finished
+    INFO the third step [2/4]
+    INFO the third step finished
+    "#);
+}
+
+/// A terminal recording every line drawn by indicatif.
+#[cfg(feature = "indicatif")]
+#[derive(Debug, Clone, Default)]
+struct RecordingTerm(Arc<std::sync::Mutex<Vec<String>>>);
+
+#[cfg(feature = "indicatif")]
+impl indicatif::TermLike for RecordingTerm {
+    fn width(&self) -> u16 {
+        80
+    }
+    fn move_cursor_up(&self, _n: usize) -> std::io::Result<()> {
+        Ok(())
+    }
+    fn move_cursor_down(&self, _n: usize) -> std::io::Result<()> {
+        Ok(())
+    }
+    fn move_cursor_right(&self, _n: usize) -> std::io::Result<()> {
+        Ok(())
+    }
+    fn move_cursor_left(&self, _n: usize) -> std::io::Result<()> {
+        Ok(())
+    }
+    fn write_line(&self, s: &str) -> std::io::Result<()> {
+        self.write_str(s)
+    }
+    fn write_str(&self, s: &str) -> std::io::Result<()> {
+        // Get rid of the colors
+        let mut line = String::new();
+        let mut in_escape = false;
+        for c in s.chars() {
+            match c {
+                '\x1b' => in_escape = true,
+                'm' if in_escape => in_escape = false,
+                c if !in_escape => line.push(c),
+                _ => (),
+            }
+        }
+        self.0
+            .lock()
+            .unwrap()
+            .extend(line.lines().map(String::from));
+        Ok(())
+    }
+    fn clear_line(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+    fn flush(&self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "indicatif")]
+#[test]
+fn indicatif_renderer() {
+    use indicatif::{MultiProgress, ProgressDrawTarget};
+
+    let term = RecordingTerm::default();
+    let progress = DefaultProgress::default();
+    let handle = progress.follow_progression_with_indicatif(MultiProgress::with_draw_target(
+        ProgressDrawTarget::term_like_with_hz(Box::new(term.clone()), 100),
+    ));
+
+    progress.update(CustomMainSteps::TheFirstStep);
+    let (atomic, unit) = AtomicCustomUnit::new(10);
+    progress.update(unit);
+    for _ in 0..10 {
+        atomic.fetch_add(1, Ordering::Relaxed);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    enum Discovery {}
+    progress.update(CustomMainSteps::TheThirdStep);
+    progress.update(VariableNameStep::<Discovery>::new(
+        "discovering files",
+        3,
+        0,
+    ));
+    std::thread::sleep(std::time::Duration::from_millis(150));
+    progress.finish();
+
+    // The renderer must stop by itself once the progress is finished.
+    handle.join().unwrap();
+
+    let lines = term.0.lock().unwrap().clone();
+    let output = lines.join("\n");
+    let drawn = |f: &dyn Fn(&str) -> bool| lines.iter().any(|line| f(line));
+
+    // The global bar is filled proportionally to the percentage
+    assert!(
+        drawn(&|line| {
+            let filled = line.matches('#').count() as f32;
+            let width = line.matches(['#', '-']).count() as f32;
+            line.starts_with("[00:00:00] ")
+                && line.ends_with(" 50.0% (eta 0s)")
+                && (filled / width - 0.5).abs() < 0.05
+        }),
+        "{output}"
+    );
+    // Every level of the stack is indented below it with a bar of 30 characters filled up to the position
+    assert!(
+        drawn(&|line| line.starts_with("  the first step ")),
+        "{output}"
+    );
+    let unit_bars: Vec<_> = lines
+        .iter()
+        .filter(|line| line.starts_with("    custom unit (") && line.ends_with("/10"))
+        .map(|line| {
+            let (line, position) = line.rsplit_once(' ').unwrap();
+            let position: usize = position.trim_end_matches("/10").parse().unwrap();
+            let bar = &line[line.len() - 30..];
+            (bar.to_string(), position)
+        })
+        .collect();
+    assert!(
+        unit_bars.iter().any(|(_, position)| *position > 0),
+        "{output}"
+    );
+    for (bar, position) in unit_bars {
+        assert_eq!(bar.matches('=').count(), position * 3, "{bar:?}");
+        assert!(bar.chars().all(|c| "=> ".contains(c)), "{bar:?}");
+    }
+    // A spinner is drawn for the steps without a total
+    assert!(
+        drawn(&|line| line.starts_with("    discovering files ")
+            && line.ends_with(" 3")
+            && !line.contains('/')
+            && !line.contains("discovering files  3")),
+        "{output}"
+    );
+    // The bars are never drawn with the default style of indicatif
+    assert!(!output.contains('░'), "{output}");
+
+    // The durations are printed once the progress is finished
+    assert!(
+        drawn(&|line| line.starts_with("the first step > custom unit => total: ")),
+        "{output}"
+    );
+    let last = lines.iter().rfind(|line| !line.trim().is_empty()).unwrap();
+    assert!(last.starts_with("Finished in "), "{output}");
+}
+
+#[test]
+fn tty_follower_refresh() {
+    for interactive in [false, true] {
+        let progress = DefaultProgress::default();
+        let buffer = SharedBuffer::default();
+        let follower = progress
+            .tty_follower()
+            .writer(buffer.clone())
+            .interactive(interactive)
+            .refresh(std::time::Duration::from_millis(50))
+            .durations(false)
+            .spawn();
+
+        // The steps change way faster than the refresh
+        let start = std::time::Instant::now();
+        while start.elapsed() < std::time::Duration::from_millis(300) {
+            progress.update(CustomMainSteps::TheFirstStep);
+            progress.update(CustomSubSteps::JustOneMore);
+        }
+        progress.finish();
+        follower.join().unwrap();
+
+        // One print at the start and at most one per refresh after that
+        let output = buffer.output();
+        let prints = match interactive {
+            true => output.matches("progress").count(),
+            false => output.lines().count(),
+        };
+        assert!((1..=8).contains(&prints), "{prints} prints: {output}");
+    }
+}
+
+/// A writer that can be read while a follower is writing in it.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn output(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+#[test]
+fn tty_follower() {
+    let progress = DefaultProgress::default();
+    let buffer = SharedBuffer::default();
+    let follower = progress
+        .tty_follower()
+        .writer(buffer.clone())
+        .refresh(std::time::Duration::from_millis(20))
+        .spawn();
+
+    progress.update(CustomMainSteps::TheFirstStep);
+    progress.update(CustomSubSteps::JustOneMore);
+    // Give some time to the follower to print the steps before finishing.
+    std::thread::sleep(std::time::Duration::from_millis(100));
+    progress.finish();
+    follower.join().unwrap();
+
+    let output = buffer.output();
+    // Nothing but plain lines since the writer is not a tty
+    assert!(!output.contains('\x1b'), "{output}");
+    assert!(
+        output.contains("the first step (0/4) > just one more (1/3)"),
+        "{output}"
+    );
+    assert!(
+        output.contains("the first step > just one more => total: "),
+        "{output}"
+    );
+    assert!(
+        output
+            .trim_end()
+            .lines()
+            .last()
+            .unwrap()
+            .starts_with("Finished in ")
+    );
+
+    // The bars fit in the width and are erased once the follower is stopped
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheThirdStep);
+    let buffer = SharedBuffer::default();
+    let follower = progress
+        .tty_follower()
+        .writer(buffer.clone())
+        .interactive(true)
+        .color(false)
+        .width(60)
+        .spawn();
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    follower.stop().unwrap();
+    assert!(!progress.is_finished());
+
+    let output = buffer.output();
+    let mut lines = output.split('\n');
+    let progress_line = lines.next().unwrap();
+    let step_line = lines.next().unwrap();
+    assert!(progress_line.starts_with("progress         ["), "{output}");
+    assert!(step_line.starts_with("  the third step ["), "{output}");
+    assert!(step_line.contains("] 2/4  50.0% "), "{output}");
+    // The bars are aligned
+    assert_eq!(progress_line.find(']'), step_line.find(']'), "{output}");
+    assert!(progress_line.chars().count() <= 60, "{output}");
+    assert!(step_line.chars().count() <= 60, "{output}");
+    // The two lines are erased
+    assert!(output.ends_with("\x1b[A\x1b[2K\x1b[A\x1b[2K"), "{output:?}");
+}
+
+#[test]
+fn dyn_progress() {
+    fn index(progress: impl Progress) {
+        progress.update(CustomMainSteps::TheFirstStep);
+        progress.update(CustomSubSteps::WeWontGoTooFarThisTime);
+        progress.update(CustomSubSteps::JustOneMore);
+        progress.update(CustomMainSteps::TheThirdStep);
+    }
+
+    let progress = DefaultProgress::default();
+    // The progress is chosen at runtime
+    let progresses: Vec<Arc<dyn DynProgress>> =
+        vec![Arc::new(NoProgress), Arc::new(progress.clone())];
+    for dyn_progress in progresses {
+        index(dyn_progress);
+    }
+    let boxed: Box<dyn DynProgress> = Box::new(progress.clone());
+    boxed.update(CustomSubSteps::WeAreDone);
+    progress.cancel();
+    assert!(boxed.dyn_is_cancelled());
+    progress.finish();
+
+    // The steps are still replaced according to their original type
+    let durations = progress.accumulated_durations();
+    assert_eq!(
+        durations.keys().collect::<Vec<_>>(),
+        [
+            "the first step > we wont go too far this time",
+            "the first step > just one more",
+            "the first step",
+            "the third step > we are done",
+            "the third step",
+        ]
+    );
+}
+
+#[test]
+fn tee_progress() {
+    let first = DefaultProgress::default();
+    let second = DefaultProgress::default();
+    let progress = TeeProgress::new().with(first.clone()).with(second.clone());
+
+    progress.update(CustomMainSteps::TheFirstStep);
+    let (atomic, unit) = AtomicCustomUnit::new(10);
+    progress.update(unit);
+    // The atomic step is shared by both progresses
+    atomic.fetch_add(4, Ordering::Relaxed);
+    progress.update(CustomMainSteps::TheThirdStep);
+    progress.update(CustomSubSteps::JustOneMore);
+
+    for inner in [&first, &second] {
+        let view = inner.as_progress_view();
+        let steps: Vec<_> = view
+            .steps
+            .iter()
+            .map(|step| (step.current_step.as_ref(), step.finished))
+            .collect();
+        assert_eq!(steps, [("the third step", 2), ("just one more", 1)]);
+        assert_eq!(
+            inner.accumulated_durations().keys().collect::<Vec<_>>(),
+            [
+                "the first step > custom unit",
+                "the first step",
+                "the third step > just one more",
+                "the third step",
+            ]
+        );
+    }
+
+    assert!(!progress.is_cancelled());
+    second.cancel();
+    assert!(progress.is_cancelled());
+}
+
+#[test]
+fn branches() {
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheFirstStep);
+    let first = progress.fork("first shard");
+    let second = progress.fork("second shard");
+    first.update(CustomSubSteps::JustOneMore);
+    // Updating a branch doesn't replace the steps of the others
+    second.update(CustomSubSteps::WeWontGoTooFarThisTime);
+    let nested = second.fork("nested");
+    nested.update(CustomSubSteps::WeAreDone);
+
+    assert_json_snapshot!(progress.as_progress_view(), { ".**.duration" => "[duration]", ".**.eta" => "[eta]", ".**.rate" => "[rate]", ".**.instantaneous" => "[throughput]", ".**.average" => "[throughput]" }, @r#"
+    {
+      "steps": [
+        {
+          "currentStep": "the first step",
+          "finished": 0,
+          "total": 4,
+          "percentage": 0.0,
+          "duration": "[duration]",
+          "eta": "[eta]",
+          "rate": "[rate]",
+          "throughput": null,
+          "branches": [
+            {
+              "name": "first shard",
+              "steps": [
+                {
+                  "currentStep": "just one more",
+                  "finished": 1,
+                  "total": 3,
+                  "percentage": 33.333336,
+                  "duration": "[duration]",
+                  "eta": "[eta]",
+                  "rate": "[rate]",
+                  "throughput": null
+                }
+              ],
+              "percentage": 33.333336
+            },
+            {
+              "name": "second shard",
+              "steps": [
+                {
+                  "currentStep": "we wont go too far this time",
+                  "finished": 0,
+                  "total": 3,
+                  "percentage": 0.0,
+                  "duration": "[duration]",
+                  "eta": "[eta]",
+                  "rate": "[rate]",
+                  "throughput": null,
+                  "branches": [
+                    {
+                      "name": "nested",
+                      "steps": [
+                        {
+                          "currentStep": "we are done",
+                          "finished": 2,
+                          "total": 3,
+                          "percentage": 66.66667,
+                          "duration": "[duration]",
+                          "eta": "[eta]",
+                          "rate": "[rate]",
+                          "throughput": null
+                        }
+                      ],
+                      "percentage": 66.66667
+                    }
+                  ]
+                }
+              ],
+              "percentage": 22.222223
+            }
+          ]
+        }
+      ],
+      "percentage": 6.9444447,
+      "duration": "[duration]",
+      "eta": "[eta]",
+      "rate": "[rate]"
+    }
+    "#);
+
+    // A finished branch counts as done
+    drop(nested);
+    second.finish();
+    let view = progress.as_progress_view();
+    assert_eq!(view.steps[0].branches.len(), 1);
+    assert!((view.percentage - (1.0 / 3.0 + 1.0) / 2.0 / 4.0 * 100.0).abs() < 0.001);
+
+    // The branches are closed when the step they're attached to leaves the stack
+    progress.update(CustomMainSteps::TheThirdStep);
+    first.update(CustomSubSteps::WeAreDone);
+    assert!(progress.as_progress_view().steps[0].branches.is_empty());
+    drop(first);
+
+    progress.finish();
+
+    // Without any step the branches are attached to the progress itself
+    let other = DefaultProgress::default();
+    let root = other.fork("root");
+    root.update(CustomSubSteps::JustOneMore);
+    let view = other.as_progress_view();
+    assert_eq!(view.branches[0].name, "root");
+    assert!((view.percentage - 100.0 / 3.0).abs() < 0.001);
+    drop(root);
+    assert!(other.as_progress_view().branches.is_empty());
+
+    // A finished branch attached to the progress still counts as done while the others are running
+    let other = DefaultProgress::default();
+    let first = other.fork("first");
+    let second = other.fork("second");
+    first.update(CustomSubSteps::WeAreDone);
+    second.update(CustomSubSteps::WeWontGoTooFarThisTime);
+    assert!((other.as_progress_view().percentage - 100.0 / 3.0).abs() < 0.001);
+    drop(first);
+    let view = other.as_progress_view();
+    assert_eq!(view.branches.len(), 1);
+    assert!((view.percentage - 50.0).abs() < 0.001);
+    drop(second);
+    // But not once they're all finished
+    let third = other.fork("third");
+    third.update(CustomSubSteps::WeWontGoTooFarThisTime);
+    let view = other.as_progress_view();
+    assert_eq!(view.branches.len(), 1);
+    assert_eq!(view.percentage, 0.0);
+
+    assert_eq!(
+        progress.accumulated_durations().keys().collect::<Vec<_>>(),
+        [
+            "the first step > we wont go too far this time > we are done",
+            "the first step > we wont go too far this time",
+            "the first step > just one more",
+            "the first step",
+            "the third step",
+        ]
+    );
+}
+
+#[test]
+fn concurrent_branches() {
+    let progress = DefaultProgress::default().with_timeline();
+    progress.update(CustomMainSteps::TheFirstStep);
+    std::thread::scope(|s| {
+        for shard in 0..8 {
+            let branch = progress.fork(format!("shard {shard}"));
+            s.spawn(move || {
+                for _ in 0..10 {
+                    branch.update(CustomSubSteps::WeWontGoTooFarThisTime);
+                    branch.update(CustomSubSteps::JustOneMore);
+                }
+            });
+        }
+    });
+    progress.update(CustomMainSteps::TheThirdStep);
+    progress.finish();
+
+    let durations = progress.accumulated_durations();
+    let calls: Vec<_> = durations
+        .iter()
+        .map(|(name, duration)| (name.as_str(), duration.calls))
+        .collect();
+    assert_eq!(
+        calls,
+        [
+            ("the first step > we wont go too far this time", 80),
+            ("the first step > just one more", 80),
+            ("the first step", 1),
+            ("the third step", 1),
+        ]
+    );
+    // Every branch runs on its own thread in the trace
+    let trace = progress.chrome_trace();
+    let threads: std::collections::HashSet<_> =
+        trace.trace_events.iter().map(|event| event.tid).collect();
+    assert_eq!(threads.len(), 9);
+}
+
+#[test]
+fn step_guards() {
+    let progress = DefaultProgress::default();
+    progress.update(CustomMainSteps::TheFirstStep);
+    {
+        let _guard = progress.enter(CustomSubSteps::WeWontGoTooFarThisTime);
+        progress.update(AtomicCustomUnit::new(3).1);
+        assert_eq!(progress.as_progress_view().steps.len(), 3);
+    }
+    // The step and its children left the stack but not its parent
+    let view = progress.as_progress_view();
+    let steps: Vec<_> = view.steps.iter().map(|step| &step.current_step).collect();
+    assert_eq!(steps, ["the first step"]);
+    let durations = progress.accumulated_durations();
+    let sub_step = durations["the first step > we wont go too far this time"].total_duration;
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    // Its duration doesn't grow anymore
+    assert_eq!(
+        progress.accumulated_durations()["the first step > we wont go too far this time"]
+            .total_duration,
+        sub_step
+    );
+
+    // The step also leaves the stack when unwinding
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _guard = progress.enter(CustomSubSteps::JustOneMore);
+        panic!("oops");
+    }));
+    assert!(result.is_err());
+    assert_eq!(progress.as_progress_view().steps.len(), 1);
+
+    // A guard doesn't pop the step that replaced its own
+    let guard = progress.enter(CustomSubSteps::JustOneMore);
+    progress.update(CustomSubSteps::WeAreDone);
+    guard.exit();
+    let view = progress.as_progress_view();
+    let steps: Vec<_> = view.steps.iter().map(|step| &step.current_step).collect();
+    assert_eq!(steps, ["the first step", "we are done"]);
+
+    // It works the same in the branches
+    let branch = progress.fork("branch");
+    let guard = branch.enter(AtomicCustomUnit::new(10).1);
+    assert_eq!(
+        progress.as_progress_view().steps[1].branches[0].steps.len(),
+        1
+    );
+    drop(guard);
+    assert!(
+        progress.as_progress_view().steps[1].branches[0]
+            .steps
+            .is_empty()
+    );
+    drop(branch);
+    progress.finish();
+
+    let durations = progress.accumulated_durations();
+    let calls: Vec<_> = durations
+        .iter()
+        .map(|(name, duration)| (name.as_str(), duration.calls))
+        .collect();
+    assert_eq!(
+        calls,
+        [
+            (
+                "the first step > we wont go too far this time > custom unit",
+                1
+            ),
+            ("the first step > we wont go too far this time", 1),
+            ("the first step > just one more", 2),
+            ("the first step > we are done > custom unit", 1),
+            ("the first step > we are done", 1),
+            ("the first step", 1),
+        ]
+    );
+}
+
 #[test]
 fn using_a_custom_provider() {
     struct CustomProgress {